@@ -1,83 +1,128 @@
 use super::interpretor::*;
-use crate::tokens::TokenType::*;
 use crate::stmts::Function;
+use crate::value::Value;
 use crate::{
-    tokens::{Literals, Token},
+    tokens::Token,
     MainError,
 };
 use crate::environment::Environment;
+use crate::gc::{self, Traced};
 use core::cell::RefCell;
+use std::fmt;
 use std::{collections::HashMap, rc::Rc};
-#[derive(Clone, Debug)]
-pub enum StorableThings {
-    Lit(Option<Literals>),
-    Func(LoxFunction),
-    Class(LoxClass),
-    Instance(LoxInstance),
-}
 
 pub trait LoxCallable: Clone {
     // type ReturnType;
     type T;
     fn arity(&mut self) -> u32;
-    fn call(&mut self, interpretor: &mut Interpretor, arguments: Vec<Option<Literals>>) -> Self::T;
+    fn call(&mut self, interpretor: &mut Interpretor, arguments: Vec<Value>) -> Self::T;
+    // Name shown for the call in stack traces
+    fn name(&self) -> String;
     #[warn(dead_code)]
     fn give_string(&self) -> String;
 }
+// Signature of a function implemented in Rust and exposed to Lox scripts
+pub type NativeFn = dyn Fn(&mut Interpretor, Vec<Value>) -> Result<Value, MainError>;
+// Runtime representation for Native Function
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    arity: u32,
+    function: Rc<NativeFn>,
+}
+impl NativeFunction {
+    pub fn new(name: String, arity: u32, function: Rc<NativeFn>) -> NativeFunction {
+        NativeFunction { name, arity, function }
+    }
+    pub fn same_as(&self, other: &NativeFunction) -> bool {
+        Rc::ptr_eq(&self.function, &other.function)
+    }
+}
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFunction({}/{})", self.name, self.arity)
+    }
+}
+impl LoxCallable for NativeFunction {
+    type T = Result<Value,MainError>;
+    fn arity(&mut self) -> u32 {
+        self.arity
+    }
+    fn call(&mut self, interpretor: &mut Interpretor, arguments: Vec<Value>) -> Self::T {
+        (self.function)(interpretor, arguments)
+    }
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn give_string(&self) -> String {
+        format!("<native fn {}>", self.name)
+    }
+}
 // Runtime representation for Function
 #[derive(Clone, Debug)]
 pub struct LoxFunction {
-    declaration: Function,
+    declaration: Rc<Function>,
     closure: Rc<RefCell<Environment>>,
     is_initializer:bool
 }
 
 impl LoxFunction {
-    pub fn new(declaration: Function, closure: Rc<RefCell<Environment>>,is_initializer:bool) -> LoxFunction {
+    pub fn new(declaration: Rc<Function>, closure: Rc<RefCell<Environment>>,is_initializer:bool) -> LoxFunction {
         LoxFunction {
             declaration,
             closure,
             is_initializer
         }
     }
-    pub fn bind(&self, instance: LoxInstance) -> LoxFunction {
+    // `this` is an instance for methods and the class itself for static methods
+    pub fn bind(&self, this: Value) -> LoxFunction {
         // Create a new environment scope
         let environment = Environment::new_scope(self.closure.clone());
         // Borrow the environment mutably and call define
-        environment.borrow_mut().define_("this".to_string(), StorableThings::Instance(instance)); // Call define on the borrowed Environment
+        environment.borrow_mut().define_("this", this); // Call define on the borrowed Environment
         // Return a new LoxFunction
-        Self::new(self.declaration.clone(), environment,self.is_initializer)
+        LoxFunction {
+            declaration: self.declaration.clone(),
+            closure: environment,
+            is_initializer: self.is_initializer
+        }
+    }
+    pub fn is_getter(&self) -> bool {
+        self.declaration.is_getter
+    }
+    pub fn closure(&self) -> &Rc<RefCell<Environment>> {
+        &self.closure
+    }
+    // Two functions are the same if they share the declaration and the captured environment
+    pub fn same_as(&self, other: &LoxFunction) -> bool {
+        Rc::ptr_eq(&self.declaration, &other.declaration) && Rc::ptr_eq(&self.closure, &other.closure)
     }
-    
-    
 }
 impl LoxCallable for LoxFunction {
-    type T = Result<Option<StorableThings>,MainError>;
+    type T = Result<Value,MainError>;
     fn arity(&mut self) -> u32 {
         self.declaration.params.len() as u32
     }
-    fn call(&mut self, interpretor: &mut Interpretor, arguments: Vec<Option<Literals>>) -> Self::T {
+    fn call(&mut self, interpretor: &mut Interpretor, arguments: Vec<Value>) -> Self::T {
         // println!("Func called");
         let env = Environment::new_scope(self.closure.clone());
-        for (i, ele) in self.declaration.params.clone().into_iter().enumerate() {
-            env.borrow_mut().define_(ele.lexeme, StorableThings::Lit(arguments[i].clone()));
-        }
-        match interpretor.execute_block(self.declaration.body.clone(), env) {
-            Err(crate::MainError::Language(v)) => {
-                if self.is_initializer{
-                    return Ok(Some(self.closure.borrow_mut().get_at(Token::new(THIS, "this".to_string(), None, 0), 0)?));
-                }
-                Ok(v)
-            },
-            _ =>{
-                if self.is_initializer{
-                    return Ok(Some(self.closure.borrow_mut().get_at(Token::new(THIS, "this".to_string(), None, 0), 0)?));
-                }
-                Ok(None)
-            },
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            env.borrow_mut().define_(&param.lexeme, argument);
+        }
+        let flow = interpretor.execute_block(self.declaration.body.clone(), env)?;
+        //An initializer always hands back `this`, even on an early `return;`
+        if self.is_initializer{
+            return self.closure.borrow().get_at(0, 0);
+        }
+        match flow {
+            ControlFlow::Return(v) => Ok(v),
+            _ => Ok(Value::Nil),
         }
     }
 
+    fn name(&self) -> String {
+        self.declaration.name.lexeme.clone()
+    }
     fn give_string(&self) -> String {
         format!("<fn {}>", self.declaration.name.lexeme)
     }
@@ -86,24 +131,57 @@ impl LoxCallable for LoxFunction {
 pub struct LoxClass {
     pub name: String,
     pub methods:HashMap<String,LoxFunction>,
-    pub superclass:Box<Option<LoxClass>>
+    // Method table of the metaclass, i.e. methods called on the class object
+    pub static_methods:HashMap<String,LoxFunction>,
+    pub superclass:Option<Rc<LoxClass>>
 }
 impl LoxClass {
-    pub fn new(name: String, methods:HashMap<String,LoxFunction>, superclass:Box<Option<LoxClass>>) -> LoxClass {
-        LoxClass { name, methods, superclass}
+    pub fn new(name: String, methods:HashMap<String,LoxFunction>, static_methods:HashMap<String,LoxFunction>, superclass:Option<Rc<LoxClass>>) -> LoxClass {
+        LoxClass { name, methods, static_methods, superclass}
+    }
+    pub fn find_static_method(&self,name:&str)->Option<LoxFunction>{
+        if let Some(s) = self.static_methods.get(name){
+            return Some(s.clone());
+        }
+        if let Some(s) = &self.superclass {
+            return s.find_static_method(name);
+        }
+        None
+    }
+    // A new instance with no fields set, the initializer still has to run on it
+    pub fn instantiate(class: &Rc<LoxClass>) -> Value {
+        let instance = Rc::new(RefCell::new(LoxInstance::new(class.clone())));
+        gc::track(&instance);
+        Value::Instance(instance)
     }
-    pub fn find_method(&mut self,name:&str)->Option<LoxFunction>{
-        if let Some(s) = self.methods.get_mut(name){
+    // Static methods are bound to the class, so `this` inside them refers to the class object
+    pub fn get(class: &Rc<LoxClass>, name: Token, interpretor: &mut Interpretor) -> Result<Value, MainError> {
+        if let Some(t) = class.find_static_method(&name.lexeme) {
+            let mut bound = t.bind(Value::Class(class.clone()));
+            if t.is_getter() {
+                return interpretor.call_checked(&mut bound, Vec::new(), &name);
+            }
+            Ok(Value::Func(bound))
+        }else {
+            Err(MainError::RuntimeError((
+                name.span,
+                name.lexeme,
+                "Undefined static method.".to_string(),
+            )))
+        }
+    }
+    pub fn find_method(&self,name:&str)->Option<LoxFunction>{
+        if let Some(s) = self.methods.get(name){
             return Some(s.clone());
         }
-        if let Some(ref mut s) = *self.superclass {
+        if let Some(s) = &self.superclass {
             return s.find_method(name);
         }
-        return None;
+        None
     }
 }
-impl LoxCallable for LoxClass {
-    type T = Result<Option<StorableThings>,MainError>;
+impl LoxCallable for Rc<LoxClass> {
+    type T = Result<Value,MainError>;
     fn arity(&mut self) -> u32 {
         // Ensure you are borrowing correctly from the methods map
         let initializer = self.find_method("init");
@@ -113,54 +191,95 @@ impl LoxCallable for LoxClass {
             0
         }
     }
-    fn call(&mut self, interpretor: &mut Interpretor, arguments: Vec<Option<Literals>>) -> Self::T {
-
-        let instance = LoxInstance::new((*self).clone());
+    fn call(&mut self, interpretor: &mut Interpretor, arguments: Vec<Value>) -> Self::T {
+        let instance = LoxClass::instantiate(self);
         let initializer = self.find_method("init");
         if let Some(i) = initializer{
             i.bind(instance.clone()).call(interpretor, arguments)?;
         }
-        Ok(Some(StorableThings::Instance(instance)))
+        Ok(instance)
+    }
+    fn name(&self) -> String {
+        self.name.clone()
     }
     fn give_string(&self) -> String {
 
         format!("<Class {}>", self.name)
     }
 }
-// Runtime representation for Instance
-#[derive(Clone, Debug)]
+// Runtime representation for Instance, instances live on the heap and are shared through Rc<RefCell<..>>
+#[derive(Debug)]
 pub struct LoxInstance {
-    class: LoxClass,
-    fields: HashMap<String, Option<StorableThings>>,
+    class: Rc<LoxClass>,
+    fields: HashMap<String, Value>,
 }
 
 impl LoxInstance {
-    pub fn new(class: LoxClass) -> LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> LoxInstance {
         LoxInstance {
             class,
             fields: HashMap::new(),
         }
     }
     pub fn give_string(&self) -> String {
-        self.class.give_string()
+        format!("{} instance", self.class.name)
     }
-    pub fn get(&mut self, name: Token) -> Result<Option<StorableThings>, MainError> {
-        if let Some(&ref t) = self.fields.get(&name.lexeme) {
+    pub fn class(&self) -> Rc<LoxClass> {
+        self.class.clone()
+    }
+    // Takes the shared handle as methods found on the class have to be bound to this very instance
+    // Getters are executed right away, which is why the interpretor is needed
+    pub fn get(instance: &Rc<RefCell<LoxInstance>>, name: Token, interpretor: &mut Interpretor) -> Result<Value, MainError> {
+        if let Some(t) = instance.borrow().fields.get(&name.lexeme) {
             return Ok(t.clone());
-        }if let Some(t) = self.class.find_method(&name.lexeme) {
-            return Ok(Some(StorableThings::Func(t.bind(self.clone()))));
+        }
+        let method = instance.borrow().class.find_method(&name.lexeme);
+        if let Some(t) = method {
+            let mut bound = t.bind(Value::Instance(instance.clone()));
+            if t.is_getter() {
+                return interpretor.call_checked(&mut bound, Vec::new(), &name);
+            }
+            Ok(Value::Func(bound))
         }else {
             // panic!("stop");
             Err(MainError::RuntimeError((
-                name.line,
+                name.span,
                 name.lexeme,
-                format!("Undefined property/mehtod."),
+                "Undefined property/mehtod.".to_string(),
             )))
         }
     }
-    
-    pub fn set(&mut self,name:Token,value:Option<StorableThings>){
+
+    pub fn set(&mut self,name:Token,value:Value){
         self.fields.insert(name.lexeme,value);
     }
 }
-
+// Methods reference the environment the class was declared in, which may hold the class itself
+impl Traced for LoxClass {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        for method in self.methods.values().chain(self.static_methods.values()) {
+            edges.push(gc::address(method.closure()));
+        }
+        if let Some(superclass) = &self.superclass {
+            edges.push(gc::address(superclass));
+        }
+        true
+    }
+    // Classes can't change, the cycles through them are broken up at the environments instead
+    fn clear(&self) {}
+}
+impl Traced for RefCell<LoxInstance> {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        let Ok(instance) = self.try_borrow() else {
+            return false;
+        };
+        edges.push(gc::address(&instance.class));
+        instance.fields.values().for_each(|value| gc::trace_value(value, edges));
+        true
+    }
+    fn clear(&self) {
+        if let Ok(mut instance) = self.try_borrow_mut() {
+            instance.fields.clear();
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//!Interactive debugger for the tree-walk interpreter
+//!
+//!The interpreter asks the hook before it executes a statement. The debugger decides from its
+//!breakpoints and the last command whether to stop there, and while stopped it reads commands in the
+//!style of gdb: stepping, breakpoints on lines, a backtrace, the variables in scope and the value of
+//!expressions evaluated in the paused frame
+use crate::diagnostics::Diagnostic;
+use crate::interpretor::{Interpretor, Output};
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::stmts::Stmt;
+use crate::value::Value;
+use crate::MainError;
+use std::collections::BTreeSet;
+use std::io::BufRead;
+
+const HELP: &str = "\
+break N (b)       stop whenever line N is reached
+delete N (d)      remove the breakpoint on line N
+info (i)          list the breakpoints
+continue (c)      run until the next breakpoint
+step (s)          run to the next statement, entering calls
+next (n)          run to the next statement in this function or its callers
+finish (fin)      run until the current function returns
+print EXPR (p)    evaluate an expression in the current frame
+env (locals)      show the variables of every scope, innermost first
+backtrace (bt)    show the calls in progress
+list (l)          show the source around the current line
+quit (q)          stop the program
+";
+
+//Consulted by the interpreter before every statement it executes
+pub trait DebugHook {
+    //An error stops the program, `MainError::Stopped` ends it without one, e.g. when it is quit from the debugger
+    fn before_statement(&mut self, interpretor: &mut Interpretor, stmt: &Stmt) -> Result<(), MainError>;
+}
+
+//When the program is stopped next, depths are the number of calls in progress
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Continue,
+    Step,
+    Next(usize),
+    Finish(usize),
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<i32>,
+    mode: Mode,
+    //Line and depth of the statement before, a line only stops once however many statements it holds
+    previous: Option<(i32, usize)>,
+    //Line the program is stopped at
+    line: i32,
+    //Lines of the script being debugged, line numbers of statements index into it
+    lines: Vec<String>,
+    input: Box<dyn BufRead>,
+    output: Output,
+    //Set once the input ends, the program then runs to completion
+    detached: bool,
+}
+
+impl Debugger {
+    //The program stops before its first statement
+    pub fn new(source: &str, input: Box<dyn BufRead>, output: Output) -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            previous: None,
+            line: 0,
+            lines: source.lines().map(str::to_string).collect(),
+            input,
+            output,
+            detached: false,
+        }
+    }
+
+    fn should_stop(&self, line: i32, depth: usize) -> bool {
+        if self.detached || self.previous == Some((line, depth)) {
+            return false;
+        }
+        if self.breakpoints.contains(&line) {
+            return true;
+        }
+        match self.mode {
+            Mode::Continue => false,
+            Mode::Step => true,
+            Mode::Next(from) => depth <= from,
+            Mode::Finish(from) => depth < from,
+        }
+    }
+
+    //Reads commands until one of them resumes the program
+    fn prompt(&mut self, interpretor: &mut Interpretor, depth: usize) -> Result<(), MainError> {
+        let stopped_by = if self.breakpoints.contains(&self.line) { "Breakpoint" } else { "Stopped" };
+        self.say(&format!("{} at line {}", stopped_by, self.line));
+        self.show_line(self.line, false);
+        loop {
+            self.write("(rlox) ");
+            let mut command = String::new();
+            match self.input.read_line(&mut command) {
+                Ok(0) | Err(_) => {
+                    self.detached = true;
+                    return Ok(());
+                }
+                Ok(_) => {}
+            }
+            let command = command.trim();
+            let (name, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+            let argument = argument.trim();
+            match name {
+                "" => {}
+                "break" | "b" => match argument.parse::<i32>() {
+                    Ok(line) => {
+                        self.breakpoints.insert(line);
+                        self.say(&format!("Breakpoint set at line {}", line));
+                    }
+                    Err(_) => self.say("Usage: break LINE"),
+                },
+                "delete" | "d" => match argument.parse::<i32>() {
+                    Ok(line) if self.breakpoints.remove(&line) => self.say(&format!("Deleted breakpoint at line {}", line)),
+                    Ok(line) => self.say(&format!("No breakpoint at line {}", line)),
+                    Err(_) => self.say("Usage: delete LINE"),
+                },
+                "info" | "i" | "breakpoints" => {
+                    if self.breakpoints.is_empty() {
+                        self.say("No breakpoints");
+                    }
+                    for line in self.breakpoints.clone() {
+                        self.say(&format!("Breakpoint at line {}", line));
+                    }
+                }
+                "continue" | "c" => return self.resume(Mode::Continue),
+                "step" | "s" => return self.resume(Mode::Step),
+                "next" | "n" => return self.resume(Mode::Next(depth)),
+                "finish" | "fin" => {
+                    if depth == 0 {
+                        self.say("Not inside a function");
+                    } else {
+                        return self.resume(Mode::Finish(depth));
+                    }
+                }
+                "print" | "p" => {
+                    if argument.is_empty() {
+                        self.say("Usage: print EXPRESSION");
+                    } else {
+                        match evaluate(interpretor, argument) {
+                            Ok(value) => self.say(&format!("{}", value)),
+                            Err(message) => self.say(&format!("Error: {}", message)),
+                        }
+                    }
+                }
+                "env" | "locals" => self.show_environment(interpretor),
+                "backtrace" | "bt" => self.show_backtrace(interpretor),
+                "list" | "l" => {
+                    let first = (self.line - 2).max(1);
+                    for line in first..=self.line + 2 {
+                        self.show_line(line, line == self.line);
+                    }
+                }
+                "help" | "h" => self.write(HELP),
+                "quit" | "q" => return Err(MainError::Stopped),
+                _ => self.say(&format!("Unknown command '{}', try 'help'", name)),
+            }
+        }
+    }
+
+    fn resume(&mut self, mode: Mode) -> Result<(), MainError> {
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn show_line(&mut self, line: i32, current: bool) {
+        let Some(text) = usize::try_from(line - 1).ok().and_then(|i| self.lines.get(i)).cloned() else {
+            return;
+        };
+        let marker = if current { "->" } else { "  " };
+        self.say(&format!("{} {:>4} | {}", marker, line, text));
+    }
+
+    //Every scope from the innermost out, globals are listed by name without the natives
+    fn show_environment(&mut self, interpretor: &Interpretor) {
+        let mut scope = Some(interpretor.env.clone());
+        while let Some(env) = scope {
+            let env = env.borrow();
+            self.say(&format!("#lev {}", env.lev));
+            if env.is_global() {
+                let mut names: Vec<&String> =
+                    env.map.keys().filter(|name| !interpretor.natives.contains_key(*name)).collect();
+                names.sort();
+                for name in names {
+                    self.say(&format!("  {} = {}", name, env.map[name]));
+                }
+            } else {
+                for (name, value) in env.names.iter().zip(&env.values) {
+                    self.say(&format!("  {} = {}", name, value));
+                }
+            }
+            scope = env.enclosing.clone();
+        }
+    }
+
+    //Innermost call first, the same order runtime errors are traced in
+    fn show_backtrace(&mut self, interpretor: &Interpretor) {
+        let mut line = self.line;
+        let mut frames = Vec::new();
+        for frame in interpretor.call_stack.iter().rev() {
+            frames.push(format!("{} (line {})", frame.function, line));
+            line = frame.call_site.line;
+        }
+        frames.push(format!("<script> (line {})", line));
+        for (i, frame) in frames.iter().enumerate() {
+            self.say(&format!("#{} {}", i, frame));
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        let mut output = self.output.borrow_mut();
+        let _ = output.write_all(text.as_bytes());
+        let _ = output.flush();
+    }
+    fn say(&mut self, text: &str) {
+        self.write(&format!("{}\n", text));
+    }
+}
+
+impl DebugHook for Debugger {
+    fn before_statement(&mut self, interpretor: &mut Interpretor, stmt: &Stmt) -> Result<(), MainError> {
+        let line = stmt.span().line;
+        let depth = interpretor.call_stack.len();
+        let stop = self.should_stop(line, depth);
+        self.previous = Some((line, depth));
+        if !stop {
+            return Ok(());
+        }
+        self.line = line;
+        self.prompt(interpretor, depth)
+    }
+}
+
+//Runs a piece of code as if it was written where the program is stopped, locals of every scope are visible
+fn evaluate(interpretor: &mut Interpretor, code: &str) -> Result<Value, String> {
+    let mut code = code.to_string();
+    if !code.ends_with(';') && !code.ends_with('}') {
+        code.push(';');
+    }
+    let first_error = |diagnostics: Vec<Diagnostic>| diagnostics.into_iter().next().map(describe).unwrap_or_default();
+    let tokens = Scanner::resume(&code, 0, 1).scan_tokens().map_err(first_error)?;
+    let mut program = Parser::new(tokens, false).parse().map_err(first_error)?;
+    //Names of the local scopes, outermost first, the resolver hands out the same slots the program uses
+    let mut scopes = Vec::new();
+    let mut scope = Some(interpretor.env.clone());
+    while let Some(env) = scope {
+        let env = env.borrow();
+        if !env.is_global() {
+            scopes.push(env.names.clone());
+        }
+        scope = env.enclosing.clone();
+    }
+    scopes.reverse();
+    Resolver::with_scopes(interpretor, scopes).resolve(&mut program).map_err(first_error)?;
+    interpretor.interpret(&mut program).map_err(|e| describe(Diagnostic::from(e)))
+}
+
+//Spans point into the code typed at the prompt or into the script, so only the message is shown
+fn describe(diagnostic: Diagnostic) -> String {
+    if diagnostic.place.is_empty() {
+        diagnostic.message
+    } else {
+        format!("{}: {}", diagnostic.place, diagnostic.message)
+    }
+}
@@ -0,0 +1,243 @@
+//!Renders errors together with the source they point at
+use crate::tokens::Span;
+use crate::MainError;
+use std::fmt::Write;
+
+const KEYWORDS: [&str; 19] = [
+    "and", "class", "else", "false", "for", "fun", "if", "nil", "or",
+    "print", "return", "super", "this", "true", "var", "while", "break", "continue", "in",
+];
+//Lines of a longer span that are shown before the rest is elided
+const MAX_LINES: usize = 4;
+
+//Phase an error was found in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Standard,
+    Scanning,
+    Parse,
+    Resolving,
+    Compile,
+    Runtime,
+}
+
+//An error ready to be shown to the user
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub kind: Kind,
+    pub message: String,
+    //Where in the source the error was found, e.g. `at "x"` or the offending lexeme
+    pub place: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(kind: Kind, message: String, place: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic { kind, message, place, span, notes: Vec::new(), help: None }
+    }
+    //E01xx scanning, E02xx parsing, E03xx resolving, E04xx runtime, E05xx compiling to bytecode, E00xx everything else
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            Kind::Standard => "E0001",
+            Kind::Scanning => "E0100",
+            Kind::Parse => "E0200",
+            Kind::Resolving => "E0300",
+            Kind::Runtime => "E0400",
+            Kind::Compile => "E0500",
+        }
+    }
+    //Runtime errors happen while the program runs, every other kind stops it from running at all
+    pub fn is_runtime(&self) -> bool {
+        self.kind == Kind::Runtime
+    }
+    //`name` is what the source is called in the report, e.g. the path of the script
+    pub fn render(&self, source: &str, name: &str, color: bool) -> String {
+        let paint = Painter { color };
+        let mut out = String::new();
+        let _ = writeln!(out, "{}{}", paint.red(&format!("error[{}]", self.code())), paint.bold(&format!(": {}", self.message)));
+        let Some(span) = self.span else {
+            self.render_footer(&mut out, &paint, 0);
+            return out;
+        };
+        let mut lines = source_lines(source, span);
+        //Trailing lines the span only covers a line break of are left out
+        while lines.len() > 1 && lines.last().is_some_and(|(_, (line_start, text))| span.end.min(line_start + text.len()) <= *line_start) {
+            lines.pop();
+        }
+        let gutter = lines.last().map(|(number, _)| number.to_string().len()).unwrap_or(1);
+        let pad = " ".repeat(gutter);
+        let _ = writeln!(out, "{}{} {}:{}:{}", pad, paint.blue("-->"), name, span.line, span.column);
+        let _ = writeln!(out, "{} {}", pad, paint.blue("|"));
+        let elide = lines.len() > MAX_LINES;
+        for (i, (number, (line_start, text))) in lines.iter().enumerate() {
+            if elide && i == MAX_LINES - 1 {
+                let _ = writeln!(out, "{}", paint.blue("..."));
+            }
+            if elide && i >= MAX_LINES - 1 && i != lines.len() - 1 {
+                continue;
+            }
+            let _ = writeln!(out, "{} {} {}", paint.blue(&format!("{:>width$}", number, width = gutter)), paint.blue("|"), text);
+            //Underline the part of this line covered by the span, a span of no width still gets one caret
+            let from = span.start.clamp(*line_start, line_start + text.len());
+            let to = span.end.clamp(from, line_start + text.len());
+            let indent: String = text[..from - line_start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+            let width = text[from - line_start..to - line_start].chars().count().max(1);
+            let mut underline = format!("{}{}", indent, "^".repeat(width));
+            if i == lines.len() - 1 && !self.place.is_empty() && source.get(span.start..span.end) != Some(self.place.as_str()) {
+                underline.push(' ');
+                underline.push_str(&self.place);
+            }
+            let _ = writeln!(out, "{} {} {}", pad, paint.blue("|"), paint.red(&underline));
+        }
+        self.render_footer(&mut out, &paint, gutter);
+        out
+    }
+    fn render_footer(&self, out: &mut String, paint: &Painter, gutter: usize) {
+        let pad = " ".repeat(gutter);
+        for note in &self.notes {
+            let _ = writeln!(out, "{} {} note: {}", pad, paint.blue("="), note);
+        }
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "{} {} {}", pad, paint.blue("="), paint.cyan(&format!("help: {}", help)));
+        }
+    }
+}
+
+//Frames of a trace beyond which the middle of it is left out
+const MAX_FRAMES: usize = 16;
+
+//Shortens the frames of a runtime error, innermost first, so runaway recursion doesn't list thousands
+//of calls: a frame repeated in a row is shown once, and only the innermost calls and the outermost
+//one are kept of a trace that is still too long
+pub(crate) fn fold_frames(frames: Vec<String>) -> Vec<String> {
+    let mut runs: Vec<(String, usize)> = Vec::new();
+    for frame in frames {
+        match runs.last_mut() {
+            Some((last, count)) if *last == frame => *count += 1,
+            _ => runs.push((frame, 1)),
+        }
+    }
+    let mut skipped = 0;
+    if runs.len() > MAX_FRAMES {
+        let outermost = runs.pop();
+        skipped = runs.drain(MAX_FRAMES - 2..).map(|(_, count)| count).sum();
+        runs.extend(outermost);
+    }
+    let last = runs.len().saturating_sub(1);
+    let mut folded = Vec::new();
+    for (i, (frame, count)) in runs.into_iter().enumerate() {
+        folded.push(frame);
+        if count > 1 {
+            folded.push(format!("... repeated {} more times", count - 1));
+        }
+        if skipped > 0 && i + 1 == last {
+            folded.push(format!("... {} more frames", skipped));
+        }
+    }
+    folded
+}
+
+//Line number, byte offset where the line starts and text of every line the span touches
+fn source_lines(source: &str, span: Span) -> Vec<(usize, (usize, &str))> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for (i, text) in source.split('\n').enumerate() {
+        let end = offset + text.len();
+        //Spans can end on the line break itself, which belongs to the line before it
+        if end >= span.start && (offset < span.end || offset <= span.start) {
+            lines.push((i + 1, (offset, text.strip_suffix('\r').unwrap_or(text))));
+        }
+        if offset > span.end {
+            break;
+        }
+        offset = end + 1;
+    }
+    lines
+}
+impl From<MainError> for Diagnostic {
+    fn from(error: MainError) -> Diagnostic {
+        let (kind, (span, place, message)) = match error {
+            MainError::Standard(e) => return Diagnostic::new(Kind::Standard, e.to_string(), String::new(), None),
+            MainError::Stopped => return Diagnostic::new(Kind::Standard, error.to_string(), String::new(), None),
+            MainError::Traced(e, trace) => {
+                let mut diagnostic = Diagnostic::from(*e);
+                diagnostic.notes.extend(trace);
+                return diagnostic;
+            }
+            MainError::ScanningError(e) => (Kind::Scanning, e),
+            MainError::ParseError(e) => (Kind::Parse, e),
+            MainError::ResolvingError(e) => (Kind::Resolving, e),
+            MainError::CompileError(e) => (Kind::Compile, e),
+            MainError::RuntimeError(e) => (Kind::Runtime, e),
+        };
+        //Errors raised outside of any token, e.g. by a native function, have no location
+        let span = if span.line > 0 { Some(span) } else { None };
+        let mut diagnostic = Diagnostic::new(kind, message, place, span);
+        if kind == Kind::Runtime && diagnostic.message.starts_with("Undeclared") {
+            diagnostic.help = did_you_mean(&diagnostic.place);
+        }
+        diagnostic
+    }
+}
+
+//Help text for a word that is most likely a misspelt keyword
+pub fn did_you_mean(word: &str) -> Option<String> {
+    if word.len() < 3 || KEYWORDS.contains(&word) {
+        return None;
+    }
+    KEYWORDS
+        .iter()
+        .map(|keyword| (edit_distance(word, keyword), *keyword))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, keyword)| format!("did you mean `{}`?", keyword))
+}
+//Levenshtein distance where swapping two neighbouring characters counts as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            rows[i][j] = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                rows[i][j] = rows[i][j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+struct Painter {
+    color: bool,
+}
+impl Painter {
+    fn paint(&self, text: &str, code: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+    fn red(&self, text: &str) -> String {
+        self.paint(text, "1;31")
+    }
+    fn blue(&self, text: &str) -> String {
+        self.paint(text, "1;34")
+    }
+    fn cyan(&self, text: &str) -> String {
+        self.paint(text, "1;36")
+    }
+    fn bold(&self, text: &str) -> String {
+        self.paint(text, "1")
+    }
+}
@@ -1,111 +1,148 @@
 use std::{collections::HashMap, cell::RefCell};
-use crate::{tokens::Literals, callable::{ StorableThings}};
+use crate::value::Value;
+use crate::gc::{self,Traced};
 use super::{tokens::*,MainError};
 use std::rc::Rc;
-use std::ops::{Deref, DerefMut};
 #[derive(Clone, Debug)]
 pub struct Environment{
-    pub map:HashMap<String,StorableThings>,
+    //Only the global scope looks variables up by name
+    pub map:HashMap<String,Value>,
+    //Local scopes keep variables in declaration order, the resolver hands out the same slots
+    pub values:Vec<Value>,
+    //Name of every local slot, only the debugger looks at them
+    pub names:Vec<String>,
     //points to parent environment
     pub enclosing:Option<Rc<RefCell<Environment>>>,
     pub lev:i32,
 }
-// Implement Deref to allow `Environment` to be treated as a `HashMap`
-impl Deref for Environment {
-    type Target = HashMap<String, StorableThings>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.map
-    }
-}
-
-// Implement DerefMut to allow mutable dereference to `HashMap`
-impl DerefMut for Environment {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.map
-    }
-}
 impl Environment{
     // For global scope
     pub fn new()->Rc<RefCell<Environment>> {
-        let x =Rc::new(RefCell::new(Environment{
+        let env = Rc::new(RefCell::new(Environment{
             map: HashMap::new(),
+            values: Vec::new(),
+            names: Vec::new(),
             lev: 1,
             enclosing: None,
         }));
-        x
+        gc::track(&env);
+        env
     }
     // For local scope
     pub fn new_scope(env: Rc<RefCell<Environment>>) ->Rc<RefCell<Environment>> {
-        let x =Rc::new(RefCell::new(Environment {
+        let scope = Rc::new(RefCell::new(Environment {
             map: HashMap::new(),
+            values: Vec::new(),
+            names: Vec::new(),
             lev: env.borrow().lev + 1,
             enclosing: Some(env.clone()),
         }));
-        x
+        gc::track(&scope);
+        scope
     }
-    pub fn define_(&mut self,name:String, value:StorableThings){
-        //Here we are not checking if the key already exists or not this allows us to redefine the same variable with var
-        self.map.insert(name,value);
+    pub fn is_global(&self)->bool{
+        self.enclosing.is_none()
     }
-    pub fn get(&self,name:Token)->Result<StorableThings,MainError>{
-        //For getting the variable
-        // println!("Getting var from env lev {}",self.lev);
-        // println!("Get");
-        if self.map.contains_key(&name.lexeme){
-            // println!("Value we got {:?}",self.map[&name.lexeme]);
-            Ok(self.map[&name.lexeme].clone())
+    pub fn define_(&mut self,name:&str, value:Value){
+        if self.is_global(){
+            //Here we are not checking if the key already exists or not this allows us to redefine the same variable with var
+            self.map.insert(name.to_string(),value);
+        }else{
+            self.values.push(value);
+            self.names.push(name.to_string());
+        }
+    }
+    pub fn get(&self,name:Token)->Result<Value,MainError>{
+        //For getting the global variable
+        if let Some(value) = self.map.get(&name.lexeme){
+            Ok(value.clone())
         }else{
             //We are making this a runtime error as making it a static error makes recursive declaration(like for functions)
             if let Some(p) = &self.enclosing{
                 return p.borrow().get(name);
             }
-            // dbg!(self.enclosing.clone(),self.map.clone());
-            // panic!("fail");
-            Err(MainError::RuntimeError((name.line,name.lexeme,"Undeclared variable or Function".to_string())))
+            Err(MainError::RuntimeError((name.span,name.lexeme,"Undeclared variable or Function".to_string())))
         }
     }
 
-    pub fn get_at(&self,name:Token,depth:usize)->Result<StorableThings,MainError>{
-        // println!("ancestor {} ",depth);
-        let temp = self.ancestor(depth)?;
-        let x = temp.borrow().get(name);
-        x
+    pub fn get_at(&self,depth:usize,slot:usize)->Result<Value,MainError>{
+        if depth == 0 {
+            return match self.values.get(slot){
+                Some(value) => Ok(value.clone()),
+                None => Err(Self::missing_slot(depth, slot)),
+            };
+        }
+        let ancestor = self.ancestor(depth)?;
+        let ancestor = ancestor.borrow();
+        match ancestor.values.get(slot){
+            Some(value) => Ok(value.clone()),
+            None => Err(Self::missing_slot(depth, slot)),
+        }
     }
-    pub fn assign_at(&mut self,name:Token,depth:usize,value:Option<Literals>)->Result<(),MainError>{
-        let temp = self.ancestor(depth)?;
-        temp.borrow_mut().map.get_mut(&name.lexeme).map(|val| { *val = StorableThings::Lit(value); });
-        Ok(())
+    pub fn assign_at(&mut self,depth:usize,slot:usize,value:Value)->Result<(),MainError>{
+        if depth == 0 {
+            return match self.values.get_mut(slot){
+                Some(val) => {*val = value; Ok(())},
+                None => Err(Self::missing_slot(depth, slot)),
+            };
+        }
+        let ancestor = self.ancestor(depth)?;
+        let mut ancestor = ancestor.borrow_mut();
+        match ancestor.values.get_mut(slot){
+            Some(val) => {*val = value; Ok(())},
+            None => Err(Self::missing_slot(depth, slot)),
+        }
     }
+    //Walks up the chain of enclosing scopes, distance has to be at least 1
     fn ancestor(&self,distance:usize)->Result<Rc<RefCell<Environment>>,MainError>{
-        let mut environment = Rc::new(RefCell::new(self.clone()));
-        // println!("Distnace from ancestor {} and env found \n",distance);
-        for i in 0..distance{
-            environment = match &environment.clone().borrow().enclosing{
+        let mut environment = match &self.enclosing{
+            Some(s) => s.clone(),
+            None => return Err(MainError::RuntimeError((Span::default(),String::new(),"No environment found at distance of 1 from current one".to_string()))),
+        };
+        for i in 1..distance{
+            let next = match &environment.borrow().enclosing{
                 None =>{
-                    return Err(MainError::RuntimeError((0,String::new(),format!("No environment found at distance of {} from current one",i))));
+                    return Err(MainError::RuntimeError((Span::default(),String::new(),format!("No environment found at distance of {} from current one",i+1))));
                 },
                 Some(s)=>s.clone(),
             };
+            environment = next;
         }
-        // environment = dbg!(environment);
         Ok(environment)
     }
-    pub fn assign(&mut self,name:Token,value:StorableThings)->Result<(),MainError>{
+    fn missing_slot(depth:usize,slot:usize)->MainError{
+        MainError::RuntimeError((Span::default(),String::new(),format!("No variable in slot {} at distance of {} from current scope",slot,depth)))
+    }
+    pub fn assign(&mut self,name:Token,value:Value)->Result<(),MainError>{
         //Assignement can't create a new variable
-        // println!("Assignment at env level {}",self.lev);
-        if self.map.contains_key(&name.lexeme){
-            self.map.get_mut(&name.lexeme).map(|val| { *val = value; });
-            // println!("New val of variable {:?}",self.map[&name.lexeme]);
-            // println!("New val in {:?}",self);
+        if let Some(val) = self.map.get_mut(&name.lexeme){
+            *val = value;
             Ok(())
         }else{
-            // println!("Assignment at env level {} failed as var don't exist so going up",self.lev);
             if let Some(p) = &mut self.enclosing{
                 return p.borrow_mut().assign(name, value);
             }
-            Err(MainError::RuntimeError((name.line,name.lexeme,"Undefined Variable".to_string())))
+            Err(MainError::RuntimeError((name.span,name.lexeme,"Undefined Variable".to_string())))
         }
     }
 
 }
+impl Traced for RefCell<Environment>{
+    fn trace(&self,edges:&mut Vec<usize>)->bool{
+        let Ok(env) = self.try_borrow() else{
+            return false;
+        };
+        env.map.values().chain(env.values.iter()).for_each(|value| gc::trace_value(value,edges));
+        if let Some(enclosing) = &env.enclosing{
+            edges.push(gc::address(enclosing));
+        }
+        true
+    }
+    fn clear(&self){
+        if let Ok(mut env) = self.try_borrow_mut(){
+            env.map.clear();
+            env.values.clear();
+            env.enclosing = None;
+        }
+    }
+}
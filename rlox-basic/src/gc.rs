@@ -0,0 +1,206 @@
+//!Tracing garbage collector for the objects scripts create
+//!
+//!Values are shared through `Rc`, which frees everything except cycles, e.g. a closure stored in the
+//!environment it captured or two instances pointing at each other. Every object that can take part in
+//!a cycle is registered here when it is created. A collection marks everything reachable from the roots
+//!and sweeps the rest by emptying it, which breaks the cycles so `Rc` can free them.
+//!
+//!The roots are the objects referenced from outside the heap: the globals, the current environment
+//!chain, the environments of calls in progress, the VM stack and values held by the host. They are found
+//!by comparing how many references an object has with how many of them come from other objects, so
+//!nothing holding a value has to be listed by hand.
+use crate::value::{Cursor, LoxMap, Value};
+use crate::vm::object::Object;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+//Collections run once this many objects are tracked, afterwards at twice what survived the last one
+const INITIAL_THRESHOLD: usize = 1024;
+
+//An object on the heap that can hold references to other objects
+pub trait Traced {
+    //Pushes the address of every object referenced directly, false if the object is borrowed right now
+    fn trace(&self, edges: &mut Vec<usize>) -> bool;
+    //Drops everything the object references, only called once nothing reachable points at it
+    fn clear(&self);
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub collections: usize,
+    //Objects tracked right after the last collection
+    pub live: usize,
+    //Objects swept by the last collection
+    pub last_freed: usize,
+    //Objects swept by all collections
+    pub total_freed: usize,
+}
+
+struct Heap {
+    objects: Vec<Weak<dyn Traced>>,
+    threshold: usize,
+    stats: GcStats,
+}
+
+thread_local! {
+    //Objects can't move between threads, so every thread has a heap of its own
+    static HEAP: RefCell<Heap> =
+        RefCell::new(Heap { objects: Vec::new(), threshold: INITIAL_THRESHOLD, stats: GcStats::default() });
+}
+
+pub fn address<T: ?Sized>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as *const () as usize
+}
+
+//Registers a newly created object with the collector
+pub fn track<T: Traced + 'static>(object: &Rc<T>) {
+    let weak: Weak<dyn Traced> = Rc::downgrade(object) as Weak<dyn Traced>;
+    HEAP.with(|heap| heap.borrow_mut().objects.push(weak));
+}
+
+//Collects once enough objects were created since the last collection
+pub fn collect_if_needed() {
+    let due = HEAP.with(|heap| {
+        let heap = heap.borrow();
+        heap.objects.len() >= heap.threshold
+    });
+    if due {
+        collect();
+    }
+}
+
+pub fn stats() -> GcStats {
+    HEAP.with(|heap| heap.borrow().stats)
+}
+
+//Runs a full collection and hands back how many objects it swept
+pub fn collect() -> usize {
+    //Objects freed by `Rc` already are dropped from the list here
+    let (tracked, objects): (usize, Vec<Rc<dyn Traced>>) = HEAP.with(|heap| {
+        let heap = heap.borrow();
+        (heap.objects.len(), heap.objects.iter().filter_map(Weak::upgrade).collect())
+    });
+    let index: HashMap<usize, usize> = objects.iter().enumerate().map(|(i, object)| (address(object), i)).collect();
+
+    //References every object gets from other objects on the heap
+    let mut children: Vec<Vec<usize>> = Vec::with_capacity(objects.len());
+    let mut internal = vec![0; objects.len()];
+    let mut pinned = vec![false; objects.len()];
+    let mut edges = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        edges.clear();
+        //What a borrowed object points at is unknown, so it has to stay along with everything it references
+        pinned[i] = !object.trace(&mut edges);
+        let targets: Vec<usize> = edges.iter().filter_map(|edge| index.get(edge).copied()).collect();
+        for &target in &targets {
+            internal[target] += 1;
+        }
+        children.push(targets);
+    }
+
+    //Mark: the list above holds one reference to each object, any reference beyond the internal ones is a root
+    let mut marked = vec![false; objects.len()];
+    let mut pending: Vec<usize> =
+        (0..objects.len()).filter(|&i| pinned[i] || Rc::strong_count(&objects[i]) - 1 > internal[i]).collect();
+    while let Some(i) = pending.pop() {
+        if !marked[i] {
+            marked[i] = true;
+            pending.extend(children[i].iter().filter(|&&child| !marked[child]));
+        }
+    }
+
+    //Sweep
+    let mut survivors = Vec::new();
+    let mut freed = 0;
+    for (object, marked) in objects.iter().zip(&marked) {
+        if *marked {
+            survivors.push(Rc::downgrade(object));
+        } else {
+            object.clear();
+            freed += 1;
+        }
+    }
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        //Objects created while sweeping, e.g. by `Drop` implementations, are kept
+        let created = heap.objects.split_off(tracked);
+        survivors.extend(created.into_iter().filter(|weak| weak.strong_count() > 0));
+        heap.stats.collections += 1;
+        heap.stats.live = survivors.len();
+        heap.stats.last_freed = freed;
+        heap.stats.total_freed += freed;
+        heap.threshold = INITIAL_THRESHOLD.max(survivors.len() * 2);
+        heap.objects = survivors;
+    });
+    //Dropping the last references to the swept objects frees them
+    drop(objects);
+    freed
+}
+
+//Objects a value refers to directly, numbers, strings and natives refer to none
+pub fn trace_value(value: &Value, edges: &mut Vec<usize>) {
+    match value {
+        Value::Func(function) => edges.push(address(function.closure())),
+        Value::Class(class) => edges.push(address(class)),
+        Value::Instance(instance) => edges.push(address(instance)),
+        Value::List(list) => edges.push(address(list)),
+        Value::Map(map) => edges.push(address(map)),
+        Value::Vm(object) => match object {
+            Object::Function(_) => {}
+            Object::Closure(closure) => edges.push(address(closure)),
+            Object::Class(class) => edges.push(address(class)),
+            Object::Instance(instance) => edges.push(address(instance)),
+            Object::BoundMethod(bound) => edges.push(address(bound)),
+            Object::Cursor(cursor) => edges.push(address(cursor)),
+        },
+        Value::Nil | Value::Boolean(_) | Value::Number(_) | Value::String(_) | Value::Native(_) => {}
+    }
+}
+
+impl Traced for RefCell<Vec<Value>> {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        let Ok(list) = self.try_borrow() else {
+            return false;
+        };
+        list.iter().for_each(|value| trace_value(value, edges));
+        true
+    }
+    fn clear(&self) {
+        if let Ok(mut list) = self.try_borrow_mut() {
+            std::mem::take(&mut *list);
+        }
+    }
+}
+impl Traced for RefCell<LoxMap> {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        let Ok(map) = self.try_borrow() else {
+            return false;
+        };
+        map.iter().for_each(|(_, value)| trace_value(value, edges));
+        true
+    }
+    fn clear(&self) {
+        if let Ok(mut map) = self.try_borrow_mut() {
+            std::mem::take(&mut *map);
+        }
+    }
+}
+impl Traced for RefCell<Cursor> {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        let Ok(cursor) = self.try_borrow() else {
+            return false;
+        };
+        match &*cursor {
+            Cursor::List(list, _) => edges.push(address(list)),
+            Cursor::Values(values) => values.as_slice().iter().for_each(|value| trace_value(value, edges)),
+            Cursor::Iterator(object) => trace_value(object, edges),
+        }
+        true
+    }
+    fn clear(&self) {
+        if let Ok(mut cursor) = self.try_borrow_mut() {
+            *cursor = Cursor::Values(Vec::new().into_iter());
+        }
+    }
+}
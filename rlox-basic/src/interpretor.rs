@@ -1,493 +1,544 @@
 use crate::callable::LoxCallable;
-use crate::callable::StorableThings;
 use crate::callable::LoxFunction;
+use crate::callable::NativeFunction;
+use crate::callable::NativeFn;
 use crate::parser::expr::*;
 use crate::parser::stmts::VisitorStmt;
-use crate::tokens::MyFloat;
-use crate::{tokens::{Literals, TokenType}, MainError};
+use crate::value::{format_number, Cursor, LoxMap, MapKey, Value};
+use crate::{tokens::TokenType, MainError};
 use TokenType::*;
 use super::environment::*;
+use super::natives;
+use crate::debugger::DebugHook;
+use crate::diagnostics::fold_frames;
+use crate::gc;
 use core::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{self, Write};
 use std::rc::Rc;
 use crate::stmts::*;
-use crate::tokens::Token;
+use crate::tokens::{Span, Token};
 use crate::callable::LoxClass;
+use crate::callable::LoxInstance;
 //Don't inport BorrowMut otherwise code would fail 
 //https://github.com/rust-lang/rust/issues/39232
 
+//How a statement finished, `Return`, `Break` and `Continue` unwind to the enclosing function or loop
+//They are kept apart from MainError so that real runtime errors are never mistaken for a return
 #[derive(Clone, Debug)]
+pub enum ControlFlow{
+    Normal,
+    Return(Value),
+    Break,
+    Continue,
+}
+
+//A call that has not returned yet, kept so that runtime errors can tell how they were reached
+#[derive(Clone, Debug)]
+pub struct CallFrame{
+    pub function:String,
+    //Where the call was made from
+    pub call_site:Span,
+}
+
+//Every call takes many Rust frames here, so the limit is far below the VM's, it fits the 8 MiB main thread
+const MAX_CALL_DEPTH:usize = 256;
+
+//Where `print` and natives doing I/O write to, shared so the host can read back what was written
+pub type Output = Rc<RefCell<dyn Write>>;
+
+#[derive(Clone)]
 pub struct Interpretor{
     pub globals:Rc<RefCell<Environment>>,
     pub env:Rc<RefCell<Environment>>,
-    pub locals:HashMap<Expr,usize>
+    //Scope distance and slot of every resolved variable, keyed by the id of the expression
+    pub locals:HashMap<usize,(usize,usize)>,
+    //Registry of functions implemented in Rust, each of them is also defined in globals
+    pub natives:HashMap<String,NativeFunction>,
+    //Calls currently being executed, the innermost one is last
+    pub call_stack:Vec<CallFrame>,
+    output:Output,
+    //Consulted before every statement while a program is being debugged
+    debugger:Option<Rc<RefCell<dyn DebugHook>>>,
 }
 
+impl Default for Interpretor{
+    fn default()->Self{
+        Self::new()
+    }
+}
 impl Interpretor{
     pub fn new()->Interpretor{
         let globals = Environment::new();
-        // globals.borrow_mut().define("clock".to_string(), St);
-        Interpretor{
+        let mut interpretor = Interpretor{
             globals:globals.clone(),
             env:globals.clone(),
-            locals:HashMap::new()
-        }
+            locals:HashMap::new(),
+            natives:HashMap::new(),
+            call_stack:Vec::new(),
+            output:Rc::new(RefCell::new(io::stdout())),
+            debugger:None,
+        };
+        natives::define_defaults(&mut interpretor);
+        interpretor
     }
-    pub fn unwind_lit(s:Option<StorableThings>)->Result<Option<Literals>,MainError>{
-        if let Some(StorableThings::Lit(l)) = s{
-            return Ok(l);
-        }if let Some(StorableThings::Class(l)) = s{
-            return Ok(Some(Literals::String(l.name)));
-        }if let Some(StorableThings::Instance(l)) = s{
-            return Ok(Some(Literals::String(l.give_string())));
-        }else{
-        //     dbg!(s);
-        // panic!("Herre Expected a Literal");
-            return Err(MainError::RuntimeError((-1,"".to_string(),"Expected a Literal".to_string())));
-       }
+    //Registers a native function and makes it visible to scripts as a global
+    pub fn define_native<F>(&mut self,name:&str,arity:u32,function:F)
+    where
+        F:Fn(&mut Interpretor,Vec<Value>)->Result<Value,MainError>+'static,
+    {
+        let function:Rc<NativeFn> = Rc::new(function);
+        let native = NativeFunction::new(name.to_string(), arity, function);
+        self.globals.borrow_mut().define_(name, Value::Native(native.clone()));
+        self.natives.insert(name.to_string(), native);
+    }
+    //Sends everything the script prints to `output` instead of stdout
+    pub fn set_output(&mut self,output:Output){
+        self.output = output;
     }
-    pub fn pack_lit(s:Option<Literals>)->Result<Option<StorableThings>,MainError>{
-        Ok(Some(StorableThings::Lit(s)))
+    //Writes a line to the output, natives doing I/O go through here as well
+    pub fn write_line(&mut self,text:&str)->Result<(),MainError>{
+        let mut output = self.output.borrow_mut();
+        writeln!(output,"{}",text).and_then(|_| output.flush()).map_err(|e| MainError::Standard(Box::new(e)))
     }
-    pub fn resolve(&mut self,expr:Expr,depth:usize){
-        self.locals.insert(expr,depth);
+    pub fn set_debugger(&mut self,debugger:Option<Rc<RefCell<dyn DebugHook>>>){
+        self.debugger = debugger;
     }
-    pub fn interpret(&mut self,program:&mut [Stmt])->Result<(), MainError>{
+    pub fn resolve(&mut self,id:usize,depth:usize,slot:usize){
+        self.locals.insert(id,(depth,slot));
+    }
+    //Hands back the value of the program when it ends with an expression statement and nil otherwise
+    pub fn interpret(&mut self,program:&mut [Stmt])->Result<Value, MainError>{
         // println!("{:?}",program);
+        let mut result = Value::Nil;
         for statement in program{
-            self.execute(statement)?;
+            //Expression statements don't go through `execute`, so the debugger is asked here
+            if let Stmt::Expression(_) = statement{
+                self.pause(statement)?;
+            }
+            result = match statement{
+                Stmt::Expression(expr) => self.evaluate(expr)?,
+                _ => {
+                    self.execute(statement)?;
+                    Value::Nil
+                }
+            };
         }
-        Ok(())
+        Ok(result)
     }
-    fn execute(&mut self,stmt:&mut Stmt)->Result<Option<StorableThings>,MainError>{
+    fn execute(&mut self,stmt:&mut Stmt)->Result<ControlFlow,MainError>{
         // println!("{:?}",stmt);
+        //Everything the statement before used is either reachable or garbage by now
+        gc::collect_if_needed();
+        self.pause(stmt)?;
         stmt.accept(self)
     }
-    fn stringify(&mut self,l:Option<Literals>)->String{
-        let mut ans = String::new();
-        match l{
-            None => {
-                // println!("null was passed in stringify");
-                ans += "nil";
-                ans
-            },
-            Some(l) => {
-                ans = match l{
-                    Literals::String(s) => s,
-                    Literals::Boolean(b) => {
-                        if b{
-                            "true".to_string()
-                        }else{
-                            "false".to_string()
-                        }
-                    }
-                    Literals::Number(MyFloat(n)) => {
-                        let mut n = n.to_string();
-                        n = if n.ends_with(".0"){
-                            n[..n.len()-2].to_string()
-                        }else{
-                            n
-                        };
-                        n
-                    }
-                };
-                ans
-            }
-        }
+    //Hands control to the debugger, which is taken out meanwhile so code it evaluates doesn't stop again
+    fn pause(&mut self,stmt:&Stmt)->Result<(),MainError>{
+        let Some(debugger) = self.debugger.take() else{
+            return Ok(());
+        };
+        let result = debugger.borrow_mut().before_statement(self,stmt);
+        self.debugger = Some(debugger);
+        result
     }
-    fn evaluate(&mut self, expr:&mut Expr) -> Result<Option<StorableThings>,MainError> {
+    fn evaluate(&mut self, expr:&mut Expr) -> Result<Value,MainError> {
         expr.accept(self)
     }
-    fn is_truthy(&mut self, value: Option<Literals>) -> bool {
-        //Lox follows Ruby’s simple rule: false and nil are falsey
-        match value {
-            Some(l) => match l {
-                Literals::Boolean(b) => b,
-                _ => true,
-            },
-            None => false,
-        }
-    }
     
-    pub fn execute_block(&mut self,list:Vec<Stmt>,new_scope:Rc<RefCell<Environment>>)->Result<(),MainError>{
+    pub fn execute_block(&mut self,list:Vec<Stmt>,new_scope:Rc<RefCell<Environment>>)->Result<ControlFlow,MainError>{
         // println!("execute block {:?}, {:?}",list,new_scope);
         let previous: Rc<RefCell<Environment>> = self.env.clone();
         self.env = new_scope;
         for mut ele in list {
             let check =self.execute(&mut ele);
-            if let Err(e) = check{
-                self.env = previous;
-                // println!("122 {:#?}",e);
-                return Err(e);
+            match check{
+                Ok(ControlFlow::Normal) => (),
+                //Errors and jumps both leave the block early, the scope has to be restored either way
+                _ => {
+                    self.env = previous;
+                    return check;
+                }
             }
         }
         self.env = previous;
-        Ok(())
+        Ok(ControlFlow::Normal)
     }
     
-    fn look_up_variable(&self,name:Token,expr:Expr)->Result<Option<StorableThings>,MainError>{
-        let distance = self.locals.get(&expr);
+    //Getters go through here as well, so they show up in stack traces like any other call
+    pub(crate) fn call_checked<C>(&mut self,callee:&mut C,arguments:Vec<Value>,paren:&Token)->Result<Value,MainError>
+    where
+        C:LoxCallable<T=Result<Value,MainError>>,
+    {
+        if arguments.len() != callee.arity() as usize {
+            return Err(MainError::RuntimeError((paren.span,paren.lexeme.clone(),format!("Expected {} argumetns but got {} .",callee.arity(),arguments.len()))));
+        }
+        //Runaway recursion is reported like on the VM before it can overflow the stack of the host
+        if self.call_stack.len() == MAX_CALL_DEPTH{
+            return Err(MainError::RuntimeError((paren.span,paren.lexeme.clone(),"Stack overflow.".to_string())));
+        }
+        self.call_stack.push(CallFrame{ function:callee.name(), call_site:paren.span });
+        let result = callee.call(self, arguments).map_err(|e| self.trace(e, paren.span));
+        self.call_stack.pop();
+        result
+    }
+    //Attaches the current call stack to a runtime error raised by the innermost call
+    fn trace(&self,error:MainError,call_site:Span)->MainError{
+        let MainError::RuntimeError((span,place,message)) = error else{
+            return error;
+        };
+        //Native functions don't know where they were called from, so their errors point at the call
+        let span = if span.line > 0 { span } else { call_site };
+        let mut line = span.line;
+        let mut trace = Vec::new();
+        for frame in self.call_stack.iter().rev(){
+            trace.push(format!("at {} (line {})", frame.function, line));
+            line = frame.call_site.line;
+        }
+        trace.push(format!("at <script> (line {})", line));
+        MainError::Traced(Box::new(MainError::RuntimeError((span,place,message))), fold_frames(trace))
+    }
+    fn cursor(&mut self,iterable:Value,stmt:&ForIn)->Result<Cursor,MainError>{
+        if let Some(cursor) = Cursor::over(&iterable){
+            return Ok(cursor);
+        }
+        match iterable{
+            Value::Instance(_) => Ok(Cursor::Iterator(self.call_protocol_method(&iterable,"iterator",&stmt.keyword)?)),
+            _ => Err(MainError::RuntimeError((stmt.iterable.span(),iterable.to_string(),"Only lists, maps, strings and instances with an iterator() method can be iterated.".to_string()))),
+        }
+    }
+    fn next_element(&mut self,cursor:&mut Cursor,keyword:&Token)->Result<Option<Value>,MainError>{
+        match cursor{
+            Cursor::List(l,i) => {
+                let element = l.borrow().get(*i).cloned();
+                *i += 1;
+                Ok(element)
+            },
+            Cursor::Values(values) => Ok(values.next()),
+            Cursor::Iterator(object) => {
+                if !self.call_protocol_method(object,"hasNext",keyword)?.is_truthy(){
+                    return Ok(None);
+                }
+                self.call_protocol_method(object,"next",keyword).map(Some)
+            },
+        }
+    }
+    //Methods of the iteration protocol are looked up on the class, fields of the same name don't count
+    fn call_protocol_method(&mut self,object:&Value,name:&str,keyword:&Token)->Result<Value,MainError>{
+        let method = match object{
+            Value::Instance(i) => i.borrow().class().find_method(name),
+            _ => None,
+        };
+        let Some(method) = method else{
+            return Err(MainError::RuntimeError((keyword.span,keyword.lexeme.clone(),format!("{} has no '{}' method to iterate with.",object,name))));
+        };
+        self.call_checked(&mut method.bind(object.clone()),Vec::new(),keyword)
+    }
+    fn look_up_variable(&self,name:Token,id:usize)->Result<Value,MainError>{
+        let distance = self.locals.get(&id);
         // dbg!(self.locals.clone());
         // println!("distance from lookup");
         // distance = dbg!(distance);
         match distance{
-            None=>{
-                match self.globals.borrow().get(name){
-                    Err(e) => Err(e),
-                    Ok(s)=>Ok(Some(s)),
-                }
-            },
-            Some(&distance)=>{
-                match self.env.borrow().get_at(name,distance){
-                    Err(e) => Err(e),
-                    Ok(s)=>Ok(Some(s)),
-                }
-            }
+            None=>self.globals.borrow().get(name),
+            Some(&(depth,slot))=>self.env.borrow().get_at(depth,slot),
         }
     }
 }
-impl VisitorExpr<Result<Option<StorableThings>,MainError>> for Interpretor{
-    fn visit_super_exp(&mut self,expr:&mut Super)->Result<Option<StorableThings>,MainError> {
+impl VisitorExpr<Result<Value,MainError>> for Interpretor{
+    fn visit_super_exp(&mut self,expr:&mut Super)->Result<Value,MainError> {
         // println!("Super interpretor expr {:#?}",expr);
-        let distance = self.locals.get(&Expr::Super(expr.clone())).unwrap();
+        let (distance,_) = self.locals[&expr.id];
         // println!("Super interpretor distance {:#?}",distance);
-        let superklass = self.env.borrow().get_at(Token { type_: SUPER, lexeme: "super".to_string(), literal: None, line: expr.keyword.line }, *distance)?;
-        let object = self.env.borrow().get_at(Token { type_: SUPER, lexeme: "this".to_string(), literal: None, line: expr.keyword.line }, *distance - 1)?;
+        //`super` and `this` are the only variables in their scopes, and `this` is always one scope closer
+        let superklass = self.env.borrow().get_at(distance, 0)?;
+        let object = self.env.borrow().get_at(distance - 1, 0)?;
         // println!("Super interpretor {:#?}",object);
-        let y =if let StorableThings::Class(mut s) = superklass{
-            
-            let method = s.find_method(&expr.method.lexeme);
-            // println!("Super interpretor method {:#?}",method);
-            if let None = method{
-                return Err(MainError::RuntimeError((expr.keyword.line,expr.keyword.lexeme.clone(),"Undefined Property".to_string())));
-            }
-            // println!("Here");
-            if let StorableThings::Instance(o) = object{
-                let x = Ok(Some(StorableThings::Func(
-                    method.unwrap().bind(o))));
-                // println!("Here");
-                // println!("x {:#?}",x);
-                x
-            }else{
-                // println!("THere");
-                // panic!("Stop");
-                Err(MainError::RuntimeError((expr.keyword.line,expr.keyword.lexeme.clone(),"Expected an instance associated with this".to_string())))
+        if let Value::Class(s) = superklass{
+            //Inside a static method `this` is the class, so the lookup happens in the static method table
+            let method = match object{
+                Value::Instance(_) => s.find_method(&expr.method.lexeme),
+                Value::Class(_) => s.find_static_method(&expr.method.lexeme),
+                _ => return Err(MainError::RuntimeError((expr.keyword.span,expr.keyword.lexeme.clone(),"Expected an instance associated with this".to_string()))),
+            };
+            let Some(method) = method else{
+                return Err(MainError::RuntimeError((expr.keyword.span,expr.keyword.lexeme.clone(),"Undefined Property".to_string())));
+            };
+            let mut bound = method.bind(object);
+            if method.is_getter(){
+                return self.call_checked(&mut bound, Vec::new(), &expr.method);
             }
+            Ok(Value::Func(bound))
         }else{
-            return Err(MainError::RuntimeError((expr.keyword.line,expr.keyword.lexeme.clone(),"Expected a class associated with super".to_string())));
-        };
-        y
+            Err(MainError::RuntimeError((expr.keyword.span,expr.keyword.lexeme.clone(),"Expected a class associated with super".to_string())))
+        }
     }
-    fn visit_this_exp(&mut self,expr:&mut This)->Result<Option<StorableThings>,MainError> {
-        self.look_up_variable(expr.keyword.clone(), Expr::This(expr.clone()))
+    fn visit_this_exp(&mut self,expr:&mut This)->Result<Value,MainError> {
+        self.look_up_variable(expr.keyword.clone(), expr.id)
     }
-    fn visit_get_exp(&mut self,expr:&mut Get)->Result<Option<StorableThings>,MainError> {
+    fn visit_get_exp(&mut self,expr:&mut Get)->Result<Value,MainError> {
         let object = self.evaluate(&mut expr.object)?;
         // println!("{:?}",object);
-        if let Some(StorableThings::Instance(mut i)) = object {
-            Ok(i.get(expr.name.clone())?)
+        if let Value::Instance(i) = object {
+            LoxInstance::get(&i, expr.name.clone(), self)
+        }else if let Value::Class(c) = object {
+            LoxClass::get(&c, expr.name.clone(), self)
         }else{
-            Err(MainError::RuntimeError((expr.name.line,expr.name.lexeme.clone(),"Only instances have properties".to_string())))
+            Err(MainError::RuntimeError((expr.name.span,expr.name.lexeme.clone(),"Only instances have properties".to_string())))
         }
     }
-    fn visit_binary_exp(&mut self, expr: &mut Binary) -> Result<Option<StorableThings>,MainError> {
+    fn visit_binary_exp(&mut self, expr: &mut Binary) -> Result<Value,MainError> {
         // println!("Binary Exp called");
         //Here the order of writing helps use to implement left ot right order of evaluation
         let left = self.evaluate(&mut expr.left)?;
         let right = self.evaluate(&mut expr.right)?;
-        let left = Self::unwind_lit(left)?;
-        let right = Self::unwind_lit(right)?;
-        let x=match expr.operator.type_{
-            MINUS=> {
-                match (left, right) {
-                    (Some(Literals::Number(MyFloat(l))), Some(Literals::Number(MyFloat(r)))) => {
-                        Some(Literals::Number(MyFloat(l-r)))
-                    },
-                    _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "Both operands are not numbers".to_string()))),
-                }
-            },
-            SLASH=>{
-                match (left, right) {
-                    (Some(Literals::Number(MyFloat(l))), Some(Literals::Number(MyFloat(r)))) => {
-                        //Handling divide by 0 is handled internally, since Lox only has on Number type which is stored as f64 so it uses Rust internal logic to hanfle by 0 which folloes IEEE 754
-                        Some(Literals::Number(MyFloat(l/r)))
-                    },
-                    _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "Both operands are not numbers".to_string()))),
-                }
-            },
-            STAR=> {
-                match (left, right) {
-                    (Some(Literals::Number(MyFloat(l))), Some(Literals::Number(MyFloat(r)))) => {
-                        Some(Literals::Number(MyFloat(l*r)))
-                    },
-                    _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "Both operands are not numbers".to_string()))),
-                }
-            },
-            PLUS=> {
-                match (left, right) {
-                    //Adding 2 numbers
-                    (Some(Literals::Number(MyFloat(l))), Some(Literals::Number(MyFloat(r)))) => {
-                        Some(Literals::Number(MyFloat(l+r)))
-                    }
-                    //Adding 2 strings
-                    (Some(Literals::String(l)), Some(Literals::String(r))) =>{
-                        Some(Literals::String(l + &r))
-                    },
-                    // Adding a string and a number
-                    (Some(Literals::String(l)), Some(Literals::Number(MyFloat(r)))) =>{
-                        let mut r= r.to_string();
-                        r = if r.ends_with(".0"){
-                            r[..r.len()-2].to_string()
-                        }else{
-                            r
-                        };
-                        Some(Literals::String(l + &r))
-                    },
-                    (Some(Literals::Number(MyFloat(l))), Some(Literals::String(r))) =>{
-                        let mut l= l.to_string();
-                        l = if l.ends_with(".0"){
-                            l[..l.len()-2].to_string()
-                        }else{
-                            l
-                        };
-                        Some(Literals::String(l + &r))
-                    },
-                    _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "Both operands are not numbers or strings".to_string()))),
-                }
-            },
-            GREATER=> {
-                match (left, right) {
-                    (Some(Literals::Number(l)), Some(Literals::Number(r))) => {
-                        Some(Literals::Boolean(l > r))
-                    },
-                    _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "Both operands are not numbers".to_string()))),
-                }
-            },
-            GreaterEqual=> {
-                match (left, right) {
-                    (Some(Literals::Number(l)), Some(Literals::Number(r))) => {
-                        Some(Literals::Boolean(l >= r))
-                    },
-                    _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "Both operands are not numbers".to_string()))),
-                }
-            },
-            LESS=> {
-                match (left, right) {
-                    (Some(Literals::Number(l)), Some(Literals::Number(r))) => {
-                        Some(Literals::Boolean(l < r))
-                    },
-                    _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "Both operands are not numbers".to_string()))),
-                }
-            },
-            LessEqual=> {
-                match (left, right) {
-                    (Some(Literals::Number(l)), Some(Literals::Number(r))) => {
-                        Some(Literals::Boolean(l <= r))
-                    },
-                    _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "Both operands are not numbers".to_string()))),
-                }
-            },
-            EqualEqual=> {
-                match (left, right) {
-                    (None, None) => {
-                        Some(Literals::Boolean(true))
-                    },
-                    (Some(l),Some(r)) => {
-                        Some(Literals::Boolean(l == r))
-                    }
-                    _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "One of the operand is a null value".to_string()))),
-                }
-            },
-            BangEqual=> {
-                match (left, right) {
-                    (None, None) => {
-                        Some(Literals::Boolean(false))
-                    },
-                    (Some(l),Some(r)) => {
-                        Some(Literals::Boolean(l != r))
-                    }
-                    _ =>return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "One of the operand is a null value".to_string()))),
-                }
-            },
-            _ => return Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(), "Binary operation can only be performed using +,-./,*,<,<=,>,>=,==,!=".to_string()))),
+        let x=match (expr.operator.type_, left, right){
+            (MINUS, Value::Number(l), Value::Number(r)) => Value::Number(l-r),
+            //Handling divide by 0 is handled internally, since Lox only has on Number type which is stored as f64 so it uses Rust internal logic to hanfle by 0 which folloes IEEE 754
+            (SLASH, Value::Number(l), Value::Number(r)) => Value::Number(l/r),
+            (STAR, Value::Number(l), Value::Number(r)) => Value::Number(l*r),
+            //Adding 2 numbers
+            (PLUS, Value::Number(l), Value::Number(r)) => Value::Number(l+r),
+            //Adding 2 strings
+            (PLUS, Value::String(l), Value::String(r)) => Value::String(l + &r),
+            // Adding a string and a number
+            (PLUS, Value::String(l), Value::Number(r)) => Value::String(l + &format_number(r)),
+            (PLUS, Value::Number(l), Value::String(r)) => Value::String(format_number(l) + &r),
+            (GREATER, Value::Number(l), Value::Number(r)) => Value::Boolean(l > r),
+            (GreaterEqual, Value::Number(l), Value::Number(r)) => Value::Boolean(l >= r),
+            (LESS, Value::Number(l), Value::Number(r)) => Value::Boolean(l < r),
+            (LessEqual, Value::Number(l), Value::Number(r)) => Value::Boolean(l <= r),
+            (EqualEqual, l, r) => Value::Boolean(l == r),
+            (BangEqual, l, r) => Value::Boolean(l != r),
+            (PLUS, _, _) => return Err(MainError::RuntimeError((expr.operator.span,expr.operator.lexeme.clone(), "Both operands are not numbers or strings".to_string()))),
+            (MINUS|SLASH|STAR|GREATER|GreaterEqual|LESS|LessEqual, _, _) => return Err(MainError::RuntimeError((expr.operator.span,expr.operator.lexeme.clone(), "Both operands are not numbers".to_string()))),
+            _ => return Err(MainError::RuntimeError((expr.operator.span,expr.operator.lexeme.clone(), "Binary operation can only be performed using +,-./,*,<,<=,>,>=,==,!=".to_string()))),
         };
-        Ok(Some(StorableThings::Lit(x)))
+        Ok(x)
     }
-    fn visit_literal_exp(&mut self, expr: &mut Literal) -> Result<Option<StorableThings>,MainError> {
+    fn visit_literal_exp(&mut self, expr: &mut Literal) -> Result<Value,MainError> {
         // println!("from visit literal:{}",input.literal.lexeme.clone());
-        Ok(Some(StorableThings::Lit(expr.literal.literal.clone())))
+        Ok(Value::from_literal(expr.literal.literal.clone()))
     }
-    fn visit_unary_exp(&mut self, expr: &mut Unary) -> Result<Option<StorableThings>,MainError> {
-        let right = self.evaluate(&mut *expr.expr)?;
-        let right = Self::unwind_lit(right)?;
+    fn visit_unary_exp(&mut self, expr: &mut Unary) -> Result<Value,MainError> {
+        let right = self.evaluate(&mut expr.expr)?;
         match expr.operator.type_ {
             TokenType::MINUS => match right {
-                Some(s) => match s {
-                    Literals::Number(MyFloat(n)) => Ok(Some(StorableThings::Lit(Some(Literals::Number(MyFloat(-n)))))),
-                    _ => Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(),"Operand must be a number".to_string())))
-                },
-                None => Ok(None),
+                Value::Number(n) => Ok(Value::Number(-n)),
+                _ => Err(MainError::RuntimeError((expr.operator.span,expr.operator.lexeme.clone(),"Operand must be a number".to_string())))
             },
-            TokenType::BANG => Ok(Some(StorableThings::Lit(Some(Literals::Boolean(!self.is_truthy(right)))))),
-            _ => Err(MainError::RuntimeError((expr.operator.line,expr.operator.lexeme.clone(),"Unary operation can only be performed with !,-".to_string()))),
+            TokenType::BANG => Ok(Value::Boolean(!right.is_truthy())),
+            _ => Err(MainError::RuntimeError((expr.operator.span,expr.operator.lexeme.clone(),"Unary operation can only be performed with !,-".to_string()))),
         }
     }
-    fn visit_logical_exp(&mut self,expr:&mut Logical)->Result<Option<StorableThings>,MainError>{
-        let left = Self::unwind_lit(self.evaluate(&mut *expr.left)?)?;
+    fn visit_logical_exp(&mut self,expr:&mut Logical)->Result<Value,MainError>{
+        let left = self.evaluate(&mut expr.left)?;
         if expr.operator.type_ == OR {
-            if self.is_truthy(left.clone()) {return Self::pack_lit(left);}
-        }else{
-            if !self.is_truthy(left.clone()) {return Self::pack_lit(left);}
+            if left.is_truthy() {return Ok(left);}
+        }else if !left.is_truthy() {
+            return Ok(left);
         }
-        self.evaluate(&mut *expr.right)
+        self.evaluate(&mut expr.right)
     }
-    fn visit_grouping_exp(&mut self, expr: &mut Grouping) -> Result<Option<StorableThings>,MainError> {
-        self.evaluate(&mut *expr.expr)
+    fn visit_grouping_exp(&mut self, expr: &mut Grouping) -> Result<Value,MainError> {
+        self.evaluate(&mut expr.expr)
     }
-    fn visit_variable_exp(&mut self,expr:&mut Variable)->Result<Option<StorableThings>,MainError>{
+    fn visit_variable_exp(&mut self,expr:&mut Variable)->Result<Value,MainError>{
         // println!("visit variable exp");
-        self.look_up_variable(expr.var.clone(), Expr::Variable((*expr).clone()))
+        self.look_up_variable(expr.var.clone(), expr.id)
     }
-    fn visit_call_exp(&mut self,expr:&mut Call)->Result<Option<StorableThings>,MainError>{
-        // println!("visit_call_exp called");
+    fn visit_call_exp(&mut self,expr:&mut Call)->Result<Value,MainError>{
         // println!("Call interpreter {:#?}",expr);
-        let callee = self.evaluate(&mut *expr.callee)?;
+        let callee = self.evaluate(&mut expr.callee)?;
         let mut arguments = Vec::new();
-        for mut ele in expr.arguments.clone() {
-            arguments.push(Self::unwind_lit(self.evaluate(&mut ele)?)?);
+        for ele in expr.arguments.iter_mut() {
+            arguments.push(self.evaluate(ele)?);
         }
-        // print!(" 2");
-        if let Some(StorableThings::Func(mut f)) = callee{
-            if arguments.len() != f.arity() as usize {
-                return Err(MainError::RuntimeError((expr.paren.line,expr.paren.lexeme.clone(),format!("Expected {} argumetns but got {} .",f.arity(),arguments.len())))); 
-            }
-            // println!(" 3");
-            return Ok(f.call(self, arguments)?);
-        }else if let Some(StorableThings::Class(mut f)) = callee{
-            return Ok(f.call(self, arguments)?);
-        }else{
-            return Err(MainError::RuntimeError((expr.paren.line,expr.paren.lexeme.clone().to_string(),"Expected a Function".to_string())));
+        match callee{
+            Value::Func(mut f) => self.call_checked(&mut f, arguments, &expr.paren),
+            Value::Native(mut f) => self.call_checked(&mut f, arguments, &expr.paren),
+            //The initializer is the frame of the call, an initializer returns the instance it was bound to
+            Value::Class(mut f) => match f.find_method("init"){
+                Some(init) => self.call_checked(&mut init.bind(LoxClass::instantiate(&f)), arguments, &expr.paren),
+                None => self.call_checked(&mut f, arguments, &expr.paren),
+            },
+            _ => Err(MainError::RuntimeError((expr.paren.span,expr.paren.lexeme.clone(),"Can only call functions and classes".to_string()))),
         }
     }
-    fn visit_assign_exp(&mut self,expr:&mut Assign)->Result<Option<StorableThings>,MainError>{
-        let value  = Self::unwind_lit(self.evaluate(&mut *expr.value)?)?;
-        let distance = self.locals.get(&Expr::Assign((*expr).clone()));
+    fn visit_assign_exp(&mut self,expr:&mut Assign)->Result<Value,MainError>{
+        let value  = self.evaluate(&mut expr.value)?;
+        let distance = self.locals.get(&expr.id);
         match distance {
             None => {
-                self.globals.borrow_mut().assign(expr.name.clone(), StorableThings::Lit(value))?;
+                self.globals.borrow_mut().assign(expr.name.clone(), value.clone())?;
             },
-            Some(&s)=>{
-                self.env.borrow_mut().assign_at(expr.name.clone(),s,value)?;
+            Some(&(depth,slot))=>{
+                self.env.borrow_mut().assign_at(depth,slot,value.clone())?;
             }
         }
-        Ok(None)      
+        Ok(value)
+    }
+    fn visit_lambda_exp(&mut self,expr:&mut Lambda)->Result<Value,MainError> {
+        Ok(Value::Func(LoxFunction::new(expr.declaration.clone(), self.env.clone(), false)))
     }
-    fn visit_set_exp(&mut self,expr:&mut Set)->Result<Option<StorableThings>,MainError> {
+    fn visit_set_exp(&mut self,expr:&mut Set)->Result<Value,MainError> {
         let object = self.evaluate(&mut expr.object)?;
-        if let Some(StorableThings::Instance(mut o)) = object{
+        if let Value::Instance(o) = object{
             let value = self.evaluate(&mut expr.value)?;
-            o.set(expr.name.clone(), value);
-            Ok(None)
+            o.borrow_mut().set(expr.name.clone(), value.clone());
+            Ok(value)
         }else{
-            Err(MainError::RuntimeError((expr.name.line,expr.name.lexeme.clone(),"Only instances have fields.".to_string())))
+            Err(MainError::RuntimeError((expr.name.span,expr.name.lexeme.clone(),"Only instances have fields.".to_string())))
         }
     }
+    fn visit_list_exp(&mut self,expr:&mut ListLiteral)->Result<Value,MainError> {
+        let mut elements = Vec::new();
+        for ele in expr.elements.iter_mut() {
+            elements.push(self.evaluate(ele)?);
+        }
+        Ok(Value::new_list(elements))
+    }
+    fn visit_map_exp(&mut self,expr:&mut MapLiteral)->Result<Value,MainError> {
+        let mut map = LoxMap::new();
+        for (key, value) in expr.entries.iter_mut() {
+            let span = key.span();
+            let key = self.evaluate(key)?;
+            let Some(key) = MapKey::from_value(&key) else{
+                return Err(MainError::RuntimeError((span,key.to_string(),"Map keys must be strings or numbers.".to_string())));
+            };
+            let value = self.evaluate(value)?;
+            map.insert(key, value);
+        }
+        Ok(Value::new_map(map))
+    }
+    fn visit_index_exp(&mut self,expr:&mut Index)->Result<Value,MainError> {
+        let object = self.evaluate(&mut expr.object)?;
+        let index = self.evaluate(&mut expr.index)?;
+        object.get_index(&index).map_err(|message| MainError::RuntimeError((expr.bracket.span,expr.bracket.lexeme.clone(),message)))
+    }
+    fn visit_index_set_exp(&mut self,expr:&mut IndexSet)->Result<Value,MainError> {
+        let object = self.evaluate(&mut expr.object)?;
+        let index = self.evaluate(&mut expr.index)?;
+        let value = self.evaluate(&mut expr.value)?;
+        object.set_index(&index,value.clone()).map_err(|message| MainError::RuntimeError((expr.bracket.span,expr.bracket.lexeme.clone(),message)))?;
+        Ok(value)
+    }
 }
 
-impl VisitorStmt<Result<Option<StorableThings>,MainError>> for Interpretor{
-    fn visit_class_stmt(&mut self,stmt:&mut Class)->Result<Option<StorableThings>,MainError> {
+impl VisitorStmt<Result<ControlFlow,MainError>> for Interpretor{
+    fn visit_expression_stmt(&mut self,expr:&mut Expr)->Result<ControlFlow,MainError>{
+        self.evaluate(expr)?;
+        Ok(ControlFlow::Normal)
+    }
+    fn visit_class_stmt(&mut self,stmt:&mut Class)->Result<ControlFlow,MainError> {
         let superclass = if let Some(v) = &stmt.superclass{
-            let superclass = self.evaluate(&mut Expr::Variable(v.clone()))?;
-            if let Some(StorableThings::Class(s)) = superclass{
+            let superclass = self.look_up_variable(v.var.clone(), v.id)?;
+            if let Value::Class(s) = superclass{
                 Some(s)
             }else{
-                return Err(MainError::RuntimeError((stmt.name.line,stmt.name.lexeme.clone(),"Superclass must be a class.".to_string())));
+                return Err(MainError::RuntimeError((stmt.name.span,stmt.name.lexeme.clone(),"Superclass must be a class.".to_string())));
             }
         }else{
             None
         };
-        self.env.borrow_mut().define_(stmt.name.lexeme.clone(), StorableThings::Lit(None));
-
-
-        if let Some(_) = stmt.superclass.clone(){
+        if let Some(s) = &superclass{
             self.env = Environment::new_scope(self.env.clone());
-            if let Some(s) = superclass.clone(){
-                self.env.borrow_mut().define_("super".to_string(), StorableThings::Class(s));
-            }
+            self.env.borrow_mut().define_("super", Value::Class(s.clone()));
         }
         let mut methods:HashMap<String, LoxFunction> = HashMap::new();
-        for ele in stmt.methods.clone() {
-            let function = LoxFunction::new(ele.clone(),self.env.clone(), ele.name.lexeme == "init");
+        for ele in stmt.methods.iter() {
+            let function = LoxFunction::new(Rc::new(ele.clone()),self.env.clone(), ele.name.lexeme == "init");
             methods.insert(ele.name.lexeme.clone(), function);
         }
-        let class = LoxClass::new(stmt.name.lexeme.clone(), methods, Box::new(superclass.clone()));
-        if let Some(_) = superclass {
+        let mut static_methods:HashMap<String, LoxFunction> = HashMap::new();
+        for ele in stmt.static_methods.iter() {
+            let function = LoxFunction::new(Rc::new(ele.clone()),self.env.clone(), false);
+            static_methods.insert(ele.name.lexeme.clone(), function);
+        }
+        let class = LoxClass::new(stmt.name.lexeme.clone(), methods, static_methods, superclass.clone());
+        if superclass.is_some() {
             // Temporarily borrow the mutable reference
             let new_env = self.env.borrow_mut().enclosing.clone().unwrap();
             // Now assign it to self.env
             self.env = new_env;
         }
-        self.env.borrow_mut().assign(stmt.name.clone(), StorableThings::Class(class))?;
-        Ok(None)
-
+        //Methods only look the class name up once they are called, so it can be defined after they are created
+        let class = Rc::new(class);
+        gc::track(&class);
+        self.env.borrow_mut().define_(&stmt.name.lexeme, Value::Class(class));
+        Ok(ControlFlow::Normal)
     }
-    fn visit_return_stmt(&mut self,stmt:&mut Return)->Result<Option<StorableThings>,MainError>{
+    fn visit_return_stmt(&mut self,stmt:&mut Return)->Result<ControlFlow,MainError>{
         let value = self.evaluate(&mut stmt.value)?;
-        return Err(MainError::Language(value));
-    }
-    fn visit_fn_stmt(&mut self,stmt:&mut Function)->Result<Option<StorableThings>,MainError>{
-        let function = LoxFunction::new(stmt.clone(),self.env.clone(), false);
-        self.env.borrow_mut().define_(stmt.name.lexeme.clone(),StorableThings::Func(function));
-        Ok(None)
-    }
-    fn visit_while_stmt(&mut self,stmt:&mut While)->Result<Option<StorableThings>,MainError>{
-        let mut val = Self::unwind_lit(self.evaluate(&mut stmt.condition)?)?;
-        let mut cond = self.is_truthy(val);
-        while cond{
-            // println!("Execution {{");
-            // println!("Current lev {:?}",self.env);
-            self.execute(&mut *stmt.body)?;
-            // println!("}}");
-            // println!("Comparison {{");
-            // println!("Current lev {:?}",self.env);
-            val = Self::unwind_lit(self.evaluate(&mut stmt.condition)?)?;
-            cond = self.is_truthy(val);
-            // println!("{}",self.is_truthy(cond.clone()));
-            // println!("}}");
+        Ok(ControlFlow::Return(value))
+    }
+    fn visit_fn_stmt(&mut self,stmt:&mut Function)->Result<ControlFlow,MainError>{
+        let function = LoxFunction::new(Rc::new(stmt.clone()),self.env.clone(), false);
+        self.env.borrow_mut().define_(&stmt.name.lexeme,Value::Func(function));
+        Ok(ControlFlow::Normal)
+    }
+    fn visit_while_stmt(&mut self,stmt:&mut While)->Result<ControlFlow,MainError>{
+        while self.evaluate(&mut stmt.condition)?.is_truthy(){
+            match self.execute(&mut stmt.body)?{
+                ControlFlow::Normal | ControlFlow::Continue => (),
+                ControlFlow::Break => break,
+                flow @ ControlFlow::Return(_) => return Ok(flow),
+            }
+            if let Some(inc) = &mut stmt.increment{
+                self.evaluate(inc)?;
+            }
+        }
+        Ok(ControlFlow::Normal)
+    }
+    fn visit_for_in_stmt(&mut self,stmt:&mut ForIn)->Result<ControlFlow,MainError>{
+        let iterable = self.evaluate(&mut stmt.iterable)?;
+        let mut cursor = self.cursor(iterable,stmt)?;
+        while let Some(element) = self.next_element(&mut cursor,&stmt.keyword)?{
+            //A fresh scope every time, so closures made in the body keep the element they saw
+            let env = Environment::new_scope(self.env.clone());
+            env.borrow_mut().define_(&stmt.name.lexeme,element);
+            //The body runs in place, the scope is restored however it is left
+            let previous = std::mem::replace(&mut self.env,env);
+            let flow = self.execute(&mut stmt.body);
+            self.env = previous;
+            match flow?{
+                ControlFlow::Normal | ControlFlow::Continue => (),
+                ControlFlow::Break => break,
+                flow @ ControlFlow::Return(_) => return Ok(flow),
+            }
         }
-        Ok(None)
+        Ok(ControlFlow::Normal)
+    }
+    fn visit_break_stmt(&mut self,_:&mut Break)->Result<ControlFlow,MainError>{
+        Ok(ControlFlow::Break)
+    }
+    fn visit_continue_stmt(&mut self,_:&mut Continue)->Result<ControlFlow,MainError>{
+        Ok(ControlFlow::Continue)
     }
-    fn visit_variable_stmt(&mut self,stmt:&mut Var)->Result<Option<StorableThings>,MainError>{
+    fn visit_variable_stmt(&mut self,stmt:&mut Var)->Result<ControlFlow,MainError>{
         //if initializer is not present,ie, value is not initialized then we assign to nil by default
-        let mut val = None;
-        if let Some(ref mut v) = stmt.initializer{
-            val = self.evaluate(v)?;
+        let val = match stmt.initializer{
+            Some(ref mut v) => self.evaluate(v)?,
+            None => Value::Nil,
         };
-       let val = if let Some(v) = val {
-            v
-       }else{
-        return Err(MainError::RuntimeError((stmt.name.line,stmt.name.lexeme.clone(),"No such var exits".to_string())));
-       };
-        self.env.borrow_mut().define_(stmt.name.lexeme.clone(),val);
-        Ok(None)
-    }
-    fn visit_if_stmt(&mut self, stmt:&mut If)->Result<Option<StorableThings>,MainError>{
-        let ans = Self::unwind_lit(self.evaluate(&mut stmt.condition)?)?;
-        if self.is_truthy(ans) {
-            self.execute(&mut *stmt.then_branch)?;
-        }else if let Some(mut s) = stmt.else_branch.clone() {
-            self.execute(&mut *s)?;
+        self.env.borrow_mut().define_(&stmt.name.lexeme,val);
+        Ok(ControlFlow::Normal)
+    }
+    fn visit_if_stmt(&mut self, stmt:&mut If)->Result<ControlFlow,MainError>{
+        if self.evaluate(&mut stmt.condition)?.is_truthy() {
+            self.execute(&mut stmt.then_branch)
+        }else if let Some(s) = &mut stmt.else_branch {
+            self.execute(s)
+        }else{
+            Ok(ControlFlow::Normal)
         }
-        Ok(None)
     }
-    fn visit_block_stmt(&mut self,stmt:&mut Block)->Result<Option<StorableThings>,MainError>{
-        self.execute_block(stmt.list.clone() ,Environment::new_scope(self.env.clone()))?;
-        Ok(None)
+    fn visit_block_stmt(&mut self,stmt:&mut Block)->Result<ControlFlow,MainError>{
+        self.execute_block(stmt.list.clone() ,Environment::new_scope(self.env.clone()))
     }
-    fn visit_print_stmt(&mut self, stmt: &mut Print) -> Result<Option<StorableThings>,MainError> {
-        let _val = Self::unwind_lit(self.evaluate(&mut stmt.expr)?)?;
-        println!("{}",self.stringify(_val));
-        Ok(None)
+    fn visit_print_stmt(&mut self, stmt: &mut Print) -> Result<ControlFlow,MainError> {
+        let val = self.evaluate(&mut stmt.expr)?;
+        self.write_line(&val.to_string())?;
+        Ok(ControlFlow::Normal)
     }
 }
-
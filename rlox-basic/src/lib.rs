@@ -1,70 +1,96 @@
-use callable::StorableThings;
-use interpretor::Interpretor;
+use interpretor::{Interpretor, Output};
+use tokens::Span;
 use resolver::Resolver;
+use vm::{compiler::Compiler, disassembler, module, Vm};
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
+use std::io::IsTerminal;
 use parser::*;
 use scanner::*;
 use std::env;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::rc::Rc;
+use std::cell::RefCell;
 use std::sync::Arc;
 pub mod tokens;
-mod interpretor;
-mod parser;
-mod scanner;
-mod environment;
-mod callable;
-mod resolver;
+pub mod interpretor;
+pub mod parser;
+pub mod scanner;
+pub mod environment;
+pub mod callable;
+pub mod resolver;
+mod natives;
+pub mod value;
+pub mod diagnostics;
+pub mod gc;
+pub mod debugger;
+pub mod vm;
+pub use value::Value;
+pub use diagnostics::Diagnostic;
 #[derive(Debug)]
 pub enum MainError {
     Standard(Box<dyn Error>),
-    Language(Option<StorableThings>),
-    ParseError((i32, String, String)),
-    RuntimeError((i32, String, String)),
-    ScanningError((i32, String, String)),
-    ResolvingError((i32, String, String))
+    ParseError((Span, String, String)),
+    RuntimeError((Span, String, String)),
+    ScanningError((Span, String, String)),
+    ResolvingError((Span, String, String)),
+    //Limits of the bytecode format the program runs into, e.g. too many locals in one function
+    CompileError((Span, String, String)),
+    //A runtime error along with the calls that led to it, innermost first
+    Traced(Box<MainError>, Vec<String>),
+    //Not an error, the program was stopped on purpose, e.g. quit from the debugger, and ends quietly
+    Stopped,
 }
 impl fmt::Display for MainError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             MainError::Standard(e) => write!(f, "Standard Error: {}", e),
-            MainError::Language(_) => { 
-                write!(
-                    f,
-                    "Language Error"
-                )
-            }
-            MainError::ParseError((line, place, message)) => {
+            MainError::ParseError((span, place, message)) => {
                 write!(
                     f,
                     "Parse Error:: [line {}] Error  {}: {}",
-                    line, place, message
+                    span.line, place, message
                 )
             }
-            MainError::RuntimeError((line, place, message)) => {
+            MainError::RuntimeError((span, place, message)) => {
                 write!(
                     f,
                     "Runtime Error:: [line {}] Error  {}: {}",
-                    line, place, message
+                    span.line, place, message
                 )
             }
-            MainError::ScanningError((line, place, message)) => {
+            MainError::ScanningError((span, place, message)) => {
                 write!(
                     f,
                     "Scanning Error:: [line {}] Error  {}: {}",
-                    line, place, message
+                    span.line, place, message
                 )
             },
-            MainError::ResolvingError((line, place, message)) => {
+            MainError::ResolvingError((span, place, message)) => {
                 write!(
                     f,
                     "Resolving Error:: [line {}] Error  {}: {}",
-                    line, place, message
+                    span.line, place, message
+                )
+            }
+            MainError::CompileError((span, place, message)) => {
+                write!(
+                    f,
+                    "Compile Error:: [line {}] Error  {}: {}",
+                    span.line, place, message
                 )
             }
+            MainError::Traced(e, trace) => {
+                write!(f, "{}", e)?;
+                for frame in trace {
+                    write!(f, "\n    {}", frame)?;
+                }
+                Ok(())
+            }
+            MainError::Stopped => write!(f, "Program stopped."),
         }
     }
 }
@@ -72,55 +98,154 @@ impl Error for MainError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             MainError::Standard(e) => Some(&**e),
-            MainError::Language(_) => None,
             MainError::ParseError(_) => None,
             MainError::RuntimeError(_) => None,
             MainError::ScanningError(_) => None,
-            MainError::ResolvingError(_) => None
+            MainError::ResolvingError(_) => None,
+            MainError::CompileError(_) => None,
+            MainError::Traced(e, _) => Some(&**e),
+            MainError::Stopped => None,
         }
     }
 }
+//Which of the two back ends runs the program, both share the scanner, parser and resolver
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+    #[default]
+    TreeWalk,
+    //Compiles to bytecode and runs it on the stack based VM
+    Bytecode,
+}
 pub struct Lox {
-    had_error: bool,
-    had_runtime_error: bool,
     interpretor: Interpretor,
+    engine: Engine,
+    //Set once code ran, functions and classes made by one engine can't be called by the other
+    started:bool,
     help:String,
+    //Everything run so far, the REPL appends each input so spans from earlier lines stay valid
+    source:String,
+    //Where every piece of `source` starts, in order, errors are shown against the run they point into
+    runs:Vec<Run>,
+    color:bool,
+}
+//A script, module or REPL input run in the session, lines of each start at 1
+struct Run {
+    start:usize,
+    //How the source is called in error reports
+    name:String,
 }
+//What code that doesn't come from a file is called in error reports
+const REPL:&str = "<repl>";
+fn engine_error(message: &str) -> Vec<Diagnostic> {
+    vec![Diagnostic::from(MainError::Standard(Box::new(io::Error::new(io::ErrorKind::Unsupported, message))))]
+}
+fn into_text(bytes: Vec<u8>) -> Result<String, Vec<Diagnostic>> {
+    String::from_utf8(bytes).map_err(|e| vec![Diagnostic::from(MainError::Standard(Box::new(e)))])
+}
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Lox {
     pub fn new() -> Lox {
         Lox {
-            had_error: false,
-            had_runtime_error: false,
             interpretor: Interpretor::new(),
-            help:String::from(".exit; -- For exiting the REPL terminal.\nPress Ctrl+C to abort the current process.")
+            engine: Engine::default(),
+            started:false,
+            help:String::from(".exit; -- For exiting the REPL terminal.\nPress Ctrl+C to abort the current process."),
+            source:String::new(),
+            runs:Vec::new(),
+            //Errors are colored only when a person is likely to read them
+            color:io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
         }
     }
-    pub fn run_file(&mut self, filepath: &String) {
-        //Opening the file
-        let mut file = match File::open((*filepath).clone()) {
-            Ok(file) => file,
-            Err(e) => {
-                panic!("Erorr opening the file:{}", e);
-            }
-        };
-        //Reading the contents of the file
-        let mut contents = String::new();
-        match file.read_to_string(&mut contents) {
-            Ok(_) => {
-                //we will manage these errors later
-                self.run(&contents, false);
-            }
-            Err(e) => {
-                self.report(MainError::Standard(Box::new(e)));
+    //Turns colors in error reports on or off
+    pub fn set_color(&mut self, color:bool) {
+        self.color = color;
+    }
+    //The engine is picked before the first run, the session keeps it from then on
+    pub fn set_engine(&mut self, engine: Engine) -> Result<(), Vec<Diagnostic>> {
+        if self.started && engine != self.engine {
+            return Err(engine_error("The engine can't be changed after code ran in the session."));
+        }
+        self.engine = engine;
+        Ok(())
+    }
+    //Runs a script, globals it defines stay around for later calls
+    //Precompiled `.loxc` modules are recognized by their header, they skip the front end and run on the VM,
+    //so a session that hasn't run anything yet switches to it
+    pub fn run_file(&mut self, filepath: &str) -> Result<Value, Vec<Diagnostic>> {
+        let bytes = self.read_file(filepath)?;
+        if module::is_module(&bytes) {
+            if self.started && self.engine != Engine::Bytecode {
+                return Err(engine_error("Modules run on the VM, they can't be loaded after code ran on the tree-walk interpreter."));
             }
-        };
-        // Indicate an error in the exit code.
-        if self.had_error {
-            std::process::exit(65)
-        };
-        if self.had_runtime_error {
-            std::process::exit(70)
-        };
+            let function = self.load_module(&bytes)?;
+            self.engine = Engine::Bytecode;
+            self.started = true;
+            return Vm::new(&mut self.interpretor).interpret(function).map_err(|e| vec![Diagnostic::from(e)]);
+        }
+        let contents = into_text(bytes)?;
+        self.run(&contents, filepath, false)
+    }
+    //Compiles a script to bytecode and saves it as a module `run_file` can execute
+    pub fn compile_file(&mut self, filepath: &str, output: &str) -> Result<(), Vec<Diagnostic>> {
+        let contents = into_text(self.read_file(filepath)?)?;
+        let origin = self.source.len();
+        let function = self.compile(&contents, filepath)?;
+        let bytes = module::encode(&function, filepath, &contents, origin).map_err(|e| vec![Diagnostic::from(e)])?;
+        File::create(output)
+            .and_then(|mut file| file.write_all(&bytes))
+            .map_err(|e| vec![Diagnostic::from(MainError::Standard(Box::new(e)))])
+    }
+    //Runs a piece of source in the same session as everything run before it
+    //and hands back the value of its last statement if that is an expression
+    pub fn eval(&mut self, source: &str) -> Result<Value, Vec<Diagnostic>> {
+        self.run(source, REPL, false)
+    }
+    //Compiles the source to bytecode without running it and lists every function in it
+    pub fn disassemble(&mut self, source: &str) -> Result<String, Vec<Diagnostic>> {
+        Ok(disassembler::disassemble(&*self.compile(source, REPL)?))
+    }
+    pub fn disassemble_file(&mut self, filepath: &str) -> Result<String, Vec<Diagnostic>> {
+        let bytes = self.read_file(filepath)?;
+        if module::is_module(&bytes) {
+            return Ok(disassembler::disassemble(&*self.load_module(&bytes)?));
+        }
+        let contents = into_text(bytes)?;
+        Ok(disassembler::disassemble(&*self.compile(&contents, filepath)?))
+    }
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpretor.globals.borrow().map.get(name).cloned()
+    }
+    //Defines a global or overwrites an existing one
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.interpretor.globals.borrow_mut().define_(name, value);
+    }
+    //Registers a function implemented in Rust that scripts can call like any other global
+    pub fn define_native<F>(&mut self, name: &str, arity: u32, function: F)
+    where
+        F: Fn(&mut Interpretor, Vec<Value>) -> Result<Value, MainError> + 'static,
+    {
+        self.interpretor.define_native(name, arity, function);
+    }
+    //Frees objects only reachable through cycles and hands back how many there were
+    pub fn collect_garbage(&mut self) -> usize {
+        gc::collect()
+    }
+    //Counts of the collector, shared by every `Lox` on the current thread
+    pub fn gc_stats(&self) -> gc::GcStats {
+        gc::stats()
+    }
+    //Sends everything scripts print to `output` instead of stdout
+    pub fn set_output(&mut self, output: Output) {
+        self.interpretor.set_output(output);
+    }
+    //Stops the tree-walk interpreter before statements the hook asks for, `None` takes it out again
+    pub fn set_debugger(&mut self, debugger: Option<Rc<RefCell<dyn debugger::DebugHook>>>) {
+        self.interpretor.set_debugger(debugger);
     }
     pub fn run_prompt(&mut self, running: Arc<AtomicBool>) {
         println!(
@@ -133,10 +258,20 @@ impl Lox {
 
             let mut input = String::new();
             match io::stdin().read_line(&mut input) {
+                //End of input, e.g. Ctrl+D
+                Ok(0) => break,
                 Ok(_) => {
-                    let input = input.trim().to_string();
-                    self.run(&input, true);
-                    self.had_error = false;
+                    let input = input.trim();
+                    if input == ".help" {
+                        println!("{}", self.help);
+                        continue;
+                    }
+                    if input == ".exit" {
+                        break;
+                    }
+                    if let Err(diagnostics) = self.run(input, REPL, true) {
+                        self.report(&diagnostics);
+                    }
                 }
                 Err(e) => {
                     eprintln!("Failed to read line: {}", e);
@@ -144,56 +279,76 @@ impl Lox {
                 }
             }
         }
-        println!("Exiting prompt due to SIGINT.");
+        println!("Exiting prompt.");
     }
-    fn run(&mut self, contents: &String,repl:bool) {
-        if contents == ".help"{
-            println!("{}", self.help);
-            return;
-        }
-        if contents == ".exit"{
-            std::process::exit(0);
-        }
-        let mut scanner = Scanner::new(contents);
-        let tokens = match scanner.scan_tokens() {
-            Ok(tokens) => tokens,
-            Err(e) => {
-                self.report(e);
-                std::process::exit(65);// Exit the function early in case of scanning error
-            }
+    fn read_file(&mut self, filepath: &str) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let mut contents = Vec::new();
+        File::open(filepath)
+            .and_then(|mut file| file.read_to_end(&mut contents))
+            .map_err(|e| vec![Diagnostic::from(MainError::Standard(Box::new(e)))])?;
+        Ok(contents)
+    }
+    //Appends a piece of source to the session and hands back the byte offset it starts at
+    fn append(&mut self, contents: &str, name: &str) -> usize {
+        let start = self.source.len();
+        self.runs.push(Run { start, name: name.to_string() });
+        self.source.push_str(contents);
+        self.source.push('\n');
+        start
+    }
+    //The source saved in the module joins the session, so errors point into it like into any other script
+    fn load_module(&mut self, bytes: &[u8]) -> Result<Rc<vm::object::Function>, Vec<Diagnostic>> {
+        let module = module::decode(bytes, self.source.len()).map_err(|e| vec![Diagnostic::from(e)])?;
+        self.append(&module.source, &module.source_name);
+        Ok(module.function)
+    }
+    fn compile(&mut self, contents: &str, name: &str) -> Result<Rc<vm::object::Function>, Vec<Diagnostic>> {
+        let mut program = self.front_end(contents, name, false)?;
+        Compiler::compile(&mut program).map_err(|e| vec![Diagnostic::from(e)])
+    }
+    fn run(&mut self, contents: &str, name: &str, repl:bool) -> Result<Value, Vec<Diagnostic>> {
+        let mut program = self.front_end(contents, name, repl)?;
+        self.started = true;
+        let result = match self.engine {
+            Engine::TreeWalk => self.interpretor.interpret(&mut program),
+            Engine::Bytecode => Compiler::compile(&mut program).and_then(|function| Vm::new(&mut self.interpretor).interpret(function)),
         };
-        let mut parser = Parser::new(tokens.clone(), repl);
-        let mut expr = parser.parse();
-        let mut resolver = Resolver::new(&mut self.interpretor);
-        match resolver.resolve(expr.clone()){
-            Ok(_)=>(),
-            Err(e)=> {
-                // println!("Here");
-                self.report(e);
-                std::process::exit(100);// Runtime Error
-            }
-        }
-        match self.interpretor.interpret(&mut expr){
-            Ok(_)=>(),
-            Err(e)=> {
-                self.report(e);
-                std::process::exit(100);// Runtime Error
-            }
+        match result {
+            Err(MainError::Stopped) => Ok(Value::Nil),
+            result => result.map_err(|e| vec![Diagnostic::from(e)]),
         }
     }
+    //Scans, parses and resolves the source, every error of the first phase that fails is handed back
+    fn front_end(&mut self, contents: &str, name: &str, repl:bool) -> Result<Vec<stmts::Stmt>, Vec<Diagnostic>> {
+        //Offsets continue after everything run before, lines start over
+        let start = self.append(contents, name);
+        let mut scanner = Scanner::resume(&self.source, start, 1);
+        let tokens = scanner.scan_tokens()?;
+        let mut parser = Parser::new(tokens, repl);
+        let mut expr = parser.parse()?;
+        let mut resolver = Resolver::new(&mut self.interpretor);
+        resolver.resolve(&mut expr)?;
+        Ok(expr)
+    }
 
-    pub fn report(&mut self, e: MainError) {
-        eprint!("{}", e);
-        match e {
-            MainError::RuntimeError(_) => {
-                self.had_runtime_error = true;
-            }
-            MainError::ParseError(_) => {
-                self.had_error = true;
-            }
-            _ => {
-                self.had_runtime_error = true;
-            }
+    //Prints the diagnostics to stderr along with the source they point at
+    pub fn report(&self, diagnostics: &[Diagnostic]) {
+        for diagnostic in diagnostics {
+            eprint!("{}", self.render(diagnostic));
         }
     }
+    //What `report` prints for the diagnostic, shown against the script or input it points into
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let Some((i, mut span)) = diagnostic.span.and_then(|span| {
+            self.runs.iter().rposition(|run| run.start <= span.start).map(|i| (i, span))
+        }) else {
+            return diagnostic.render("", REPL, self.color);
+        };
+        let run = &self.runs[i];
+        let end = self.runs.get(i + 1).map_or(self.source.len(), |next| next.start);
+        span.start -= run.start;
+        span.end = span.end.clamp(run.start, end) - run.start;
+        let local = Diagnostic { span: Some(span), ..diagnostic.clone() };
+        local.render(&self.source[run.start..end], &run.name, self.color)
+    }
 }
@@ -1,9 +1,15 @@
-use rlox_basic::Lox;
+use rlox_basic::diagnostics::{Diagnostic, Kind};
+use rlox_basic::debugger::Debugger;
+use rlox_basic::{Engine, Lox};
 use signal_hook::consts::SIGINT;
 use signal_hook::iterator::Signals;
 use std::env;
+use std::path::Path;
+use std::cell::RefCell;
+use std::fs;
 use std::io::{self, Write};
 use std::sync::atomic::{AtomicBool};
+use std::rc::Rc;
 use std::sync::Arc;
 use std::thread;
 
@@ -14,7 +20,7 @@ fn main() {
 
     // Set up a separate thread to handle SIGINT
     thread::spawn(move || {
-        let mut signals = Signals::new(&[SIGINT]).expect("Failed to create signals iterator");
+        let mut signals = Signals::new([SIGINT]).expect("Failed to create signals iterator");
         for sig in signals.forever() {
             if sig == SIGINT {
                 print!("\nCaught KeyboardInterrupt (SIGINT), but continuing...\n>> ");
@@ -23,20 +29,102 @@ fn main() {
         }
     });
 
-    // Collect the command-line arguments
-    let args: Vec<String> = env::args().collect();
+    // Collect the command-line arguments, flags are taken out before looking for a script
+    let mut args: Vec<String> = env::args().collect();
     let mut interpreter = Lox::new();
+    if let Some(i) = args.iter().position(|arg| arg == "--no-color") {
+        args.remove(i);
+        interpreter.set_color(false);
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--vm") {
+        args.remove(i);
+        interpreter.set_engine(Engine::Bytecode).expect("Nothing ran yet");
+    }
+    // Print the bytecode of the script instead of running it
+    let disassemble = match args.iter().position(|arg| arg == "--disassemble") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    // Save the bytecode of the script as a module instead of running it, `-o` names the module
+    let compile = match args.iter().position(|arg| arg == "--compile") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    // Run the script under the debugger, only the tree-walk interpreter can be stopped
+    let debug = match args.iter().position(|arg| arg == "--debug") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    let output = match args.iter().position(|arg| arg == "-o") {
+        Some(i) if i + 1 < args.len() => {
+            args.remove(i);
+            Some(args.remove(i))
+        }
+        Some(_) => usage(),
+        None => None,
+    };
 
-    if args.len() > 2 {
+    if args.len() > 2 || (output.is_some() && !compile) || (debug && (args.len() < 2 || compile || disassemble)) {
         // If args are too many then exit the code
-        eprintln!("Usage: jlox [script]");
-        std::process::exit(64);
+        usage();
+    } else if disassemble || compile {
+        if args.len() < 2 {
+            usage();
+        }
+        let result = if compile {
+            let output = output.unwrap_or_else(|| Path::new(&args[1]).with_extension("loxc").display().to_string());
+            interpreter.compile_file(&args[1], &output)
+        } else {
+            interpreter.disassemble_file(&args[1]).map(|listing| print!("{}", listing))
+        };
+        if let Err(diagnostics) = result {
+            interpreter.report(&diagnostics);
+            std::process::exit(exit_code(&diagnostics));
+        }
     } else if args.len() == 2 {
         // Run file
         println!("Running file: {}", &args[1]);
-        interpreter.run_file(&args[1]);
+        if debug {
+            interpreter.set_engine(Engine::TreeWalk).expect("Nothing ran yet");
+            // Lines the debugger shows, a file that can't be read is reported when it is run
+            let source = fs::read_to_string(&args[1]).unwrap_or_default();
+            let input = Box::new(io::BufReader::new(io::stdin()));
+            let debugger = Debugger::new(&source, input, Rc::new(RefCell::new(io::stdout())));
+            interpreter.set_debugger(Some(Rc::new(RefCell::new(debugger))));
+        }
+        if let Err(diagnostics) = interpreter.run_file(&args[1]) {
+            interpreter.report(&diagnostics);
+            std::process::exit(exit_code(&diagnostics));
+        }
     } else {
         // Run prompt and handle Ctrl+C
         interpreter.run_prompt(running);
     }
 }
+
+fn usage() -> ! {
+    eprintln!("Usage: jlox [--no-color] [--vm] [--disassemble] [script]");
+    eprintln!("       jlox [--no-color] --debug script");
+    eprintln!("       jlox --compile script [-o module.loxc]");
+    std::process::exit(64);
+}
+
+// Indicate the kind of error in the exit code.
+fn exit_code(diagnostics: &[Diagnostic]) -> i32 {
+    if diagnostics.iter().any(|d| d.kind == Kind::Standard) {
+        66
+    } else if diagnostics.iter().any(|d| d.kind != Kind::Runtime) {
+        65
+    } else {
+        70
+    }
+}
@@ -0,0 +1,130 @@
+//!Functions implemented in Rust which are available to every Lox script
+use crate::gc;
+use crate::interpretor::Interpretor;
+use crate::value::{MapKey, Value};
+use crate::MainError;
+use crate::tokens::Span;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn define_defaults(interpretor:&mut Interpretor){
+    interpretor.define_native("clock", 0, clock);
+    interpretor.define_native("readLine", 0, read_line);
+    interpretor.define_native("str", 1, str);
+    interpretor.define_native("num", 1, num);
+    interpretor.define_native("len", 1, len);
+    interpretor.define_native("push", 2, push);
+    interpretor.define_native("pop", 1, pop);
+    interpretor.define_native("slice", 3, slice);
+    interpretor.define_native("keys", 1, keys);
+    interpretor.define_native("has", 2, has);
+    interpretor.define_native("remove", 2, remove);
+    interpretor.define_native("gc", 0, collect_garbage);
+}
+//Seconds elapsed since the UNIX epoch, useful for benchmarking
+fn clock(_:&mut Interpretor,_:Vec<Value>)->Result<Value,MainError>{
+    match SystemTime::now().duration_since(UNIX_EPOCH){
+        Ok(d) => Ok(Value::Number(d.as_secs_f64())),
+        Err(e) => Err(MainError::Standard(Box::new(e))),
+    }
+}
+//Runs the garbage collector right away and returns how many objects it freed
+fn collect_garbage(_:&mut Interpretor,_:Vec<Value>)->Result<Value,MainError>{
+    Ok(Value::Number(gc::collect() as f64))
+}
+//Reads a single line from stdin without the trailing newline, nil is returned at the end of input
+fn read_line(_:&mut Interpretor,_:Vec<Value>)->Result<Value,MainError>{
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line){
+        Ok(0) => Ok(Value::Nil),
+        Ok(_) => Ok(Value::String(line.trim_end_matches(['\n','\r']).to_string())),
+        Err(e) => Err(MainError::Standard(Box::new(e))),
+    }
+}
+//Converts any value into its printable representation
+fn str(_:&mut Interpretor,arguments:Vec<Value>)->Result<Value,MainError>{
+    Ok(Value::String(arguments[0].to_string()))
+}
+//Converts a string into a number, nil is returned if the string is not a valid number
+fn num(_:&mut Interpretor,arguments:Vec<Value>)->Result<Value,MainError>{
+    match &arguments[0]{
+        Value::Number(n) => Ok(Value::Number(*n)),
+        Value::String(s) => match s.trim().parse::<f64>(){
+            Ok(n) => Ok(Value::Number(n)),
+            Err(_) => Ok(Value::Nil),
+        },
+        _ => Err(MainError::RuntimeError((Span::default(),"num".to_string(),"Argument must be a string or a number".to_string()))),
+    }
+}
+//Number of elements in a list, entries in a map or characters in a string
+fn len(_:&mut Interpretor,arguments:Vec<Value>)->Result<Value,MainError>{
+    match &arguments[0]{
+        Value::List(l) => Ok(Value::Number(l.borrow().len() as f64)),
+        Value::Map(m) => Ok(Value::Number(m.borrow().len() as f64)),
+        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+        _ => Err(MainError::RuntimeError((Span::default(),"len".to_string(),"Argument must be a list, a map or a string".to_string()))),
+    }
+}
+//Appends a value to the end of a list
+fn push(_:&mut Interpretor,arguments:Vec<Value>)->Result<Value,MainError>{
+    let mut arguments = arguments.into_iter();
+    match (arguments.next(), arguments.next()){
+        (Some(Value::List(l)), Some(value)) => {
+            l.borrow_mut().push(value);
+            Ok(Value::Nil)
+        }
+        _ => Err(MainError::RuntimeError((Span::default(),"push".to_string(),"First argument must be a list".to_string()))),
+    }
+}
+//Removes the last value of a list and hands it back
+fn pop(_:&mut Interpretor,arguments:Vec<Value>)->Result<Value,MainError>{
+    let Value::List(l) = &arguments[0] else{
+        return Err(MainError::RuntimeError((Span::default(),"pop".to_string(),"Argument must be a list".to_string())));
+    };
+    match l.borrow_mut().pop(){
+        Some(value) => Ok(value),
+        None => Err(MainError::RuntimeError((Span::default(),"pop".to_string(),"Can't pop from an empty list".to_string()))),
+    }
+}
+//Copy of the elements or characters from `start` up to, but not including, `end`
+fn slice(_:&mut Interpretor,arguments:Vec<Value>)->Result<Value,MainError>{
+    //The end may point just past the last element
+    let bounds = |len:usize| match (arguments[1].as_index(len + 1), arguments[2].as_index(len + 1)){
+        (Some(start), Some(end)) if start <= end => Ok((start, end)),
+        _ => Err(MainError::RuntimeError((Span::default(),"slice".to_string(),"Slice bounds out of range".to_string()))),
+    };
+    match &arguments[0]{
+        Value::List(l) => {
+            let l = l.borrow();
+            let (start, end) = bounds(l.len())?;
+            Ok(Value::new_list(l[start..end].to_vec()))
+        }
+        Value::String(s) => {
+            let chars:Vec<char> = s.chars().collect();
+            let (start, end) = bounds(chars.len())?;
+            Ok(Value::String(chars[start..end].iter().collect()))
+        }
+        _ => Err(MainError::RuntimeError((Span::default(),"slice".to_string(),"First argument must be a list or a string".to_string()))),
+    }
+}
+//Keys of a map as a new list, in the order they were inserted
+fn keys(_:&mut Interpretor,arguments:Vec<Value>)->Result<Value,MainError>{
+    match &arguments[0]{
+        Value::Map(m) => Ok(Value::new_list(m.borrow().iter().map(|(k, _)| k.to_value()).collect())),
+        _ => Err(MainError::RuntimeError((Span::default(),"keys".to_string(),"Argument must be a map".to_string()))),
+    }
+}
+//Whether the map has an entry for the key, keys that can't be in a map are simply not there
+fn has(_:&mut Interpretor,arguments:Vec<Value>)->Result<Value,MainError>{
+    match &arguments[0]{
+        Value::Map(m) => Ok(Value::Boolean(MapKey::from_value(&arguments[1]).is_some_and(|k| m.borrow().contains_key(&k)))),
+        _ => Err(MainError::RuntimeError((Span::default(),"has".to_string(),"First argument must be a map".to_string()))),
+    }
+}
+//Removes the entry for the key and hands back its value, nil is returned if there was none
+fn remove(_:&mut Interpretor,arguments:Vec<Value>)->Result<Value,MainError>{
+    match &arguments[0]{
+        Value::Map(m) => Ok(MapKey::from_value(&arguments[1]).and_then(|k| m.borrow_mut().remove(&k)).unwrap_or(Value::Nil)),
+        _ => Err(MainError::RuntimeError((Span::default(),"remove".to_string(),"First argument must be a map".to_string()))),
+    }
+}
@@ -4,11 +4,18 @@ use super::tokens::*;
 use super::tokens::TokenType::*;
 use expr::*;
 use super::MainError;
+use crate::diagnostics::{self, Diagnostic};
 use crate::stmts::*;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+//Expression ids have to stay unique across parsers as the REPL keeps resolving into the same interpretor
+static NEXT_EXPR_ID:AtomicUsize = AtomicUsize::new(0);
 pub struct Parser{
     current:usize,
     tokens:Vec<Token>,
     repl:bool,
+    //Errors found so far, parsing carries on after each of them
+    errors:Vec<Diagnostic>,
 }
 impl Parser {
     pub fn new(
@@ -19,9 +26,11 @@ impl Parser {
             current:0,
             tokens,
             repl,
+            errors:Vec::new(),
         }
     }
-    pub fn parse(&mut self)->Vec<Stmt>{
+    //Parsing carries on after an error, the program is only handed out if there were none
+    pub fn parse(&mut self)->Result<Vec<Stmt>,Vec<Diagnostic>>{
         //Prgram is a list of statements
         let mut program:Vec<Stmt> = Vec::new();
         while !self.is_at_end(){
@@ -29,18 +38,32 @@ impl Parser {
             match val{
                 Ok(decl)=>program.push(decl),
                 Err(m)=>{
-                    eprint!("{}", m);
+                    self.record(m);
                     self.synchronize();
                 }
             }
         }
-        program
+        if self.errors.is_empty(){
+            Ok(program)
+        }else{
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+    fn record(&mut self,error:MainError){
+        let mut diagnostic = Diagnostic::from(error);
+        //A misspelt keyword is read as a variable, so the error shows up on the token after it
+        if self.current > 0 && self.previous().type_ == IDENTIFIER {
+            diagnostic.help = diagnostics::did_you_mean(&self.previous().lexeme);
+        }
+        self.errors.push(diagnostic);
     }
     fn declaration(&mut self)->Result<Stmt, MainError>{
         if self.match_(&[VAR]){
             self.var_declaration()
-        }else if self.match_(&[FUN]){
-            self.function("function")
+        }else if self.check(FUN) && !self.check_next(LeftParen){
+            //`fun (` starts a lambda expression rather than a declaration
+            let start = self.advance().span;
+            self.function("function", start)
         }else if self.match_(&[CLASS]){
             self.class_declration()
         }else{
@@ -48,48 +71,72 @@ impl Parser {
         }
     }
     fn class_declration(&mut self)->Result<Stmt, MainError>{
+        let start = self.previous().span;
         let name = self.consume(IDENTIFIER, "Expected a class name.")?;
         let superclass = if self.match_(&[LESS]){
             self.consume(IDENTIFIER, "Expected a super class.")?;
-            Some(Variable::new(self.previous()))
+            let superclass = self.previous();
+            Some(Variable::new(self.next_id(),superclass.clone(),superclass.span))
         }else{
             None
         };
         self.consume(LeftBrace, "Expected '{' before class body.\n")?;
         let mut methods = Vec::new();
+        let mut static_methods = Vec::new();
         while !self.check(RightBrace) && !self.is_at_end() {
-            if let Stmt::Function(temp) = self.function("method")?{
-                methods.push(temp);
+            if self.match_(&[CLASS]){
+                let start = self.previous().span;
+                static_methods.push(self.method(start)?);
             }else{
-                return Err(MainError::ParseError((self.peek().line,"".to_string(),"Only methods allowed inside the class".to_string())));
+                let start = self.peek().span;
+                methods.push(self.method(start)?);
             }
         }
         self.consume(RightBrace, "Expected '}' after class body.\n")?;
-        let x= Stmt::Class(Class::new(name, methods,superclass));
+        let x= Stmt::Class(Class::new(name, methods, static_methods, superclass, self.span_from(start)));
         // println!("Class {:#?}",x);
         Ok(x)
     }
-    fn function(&mut self,kind:&str)->Result<Stmt, MainError>{
-        let name = self.consume(IDENTIFIER, &format!("Expected {} name.",kind.to_string()))?;
-        self.consume(LeftParen, &format!("Expected \"(\" after {} name.",kind.to_string()))?;
+    fn method(&mut self,start:Span)->Result<Function, MainError>{
+        //A method name directly followed by its body declares a getter
+        if self.check(IDENTIFIER) && self.check_next(LeftBrace){
+            let name = self.advance();
+            self.consume(LeftBrace, "Expected '{' before getter body.")?;
+            let body = self.block_statement()?;
+            Ok(Function::getter(name, body, self.span_from(start)))
+        }else if let Stmt::Function(temp) = self.function("method", start)?{
+            Ok(temp)
+        }else{
+            Err(MainError::ParseError((self.peek().span,"".to_string(),"Only methods allowed inside the class".to_string())))
+        }
+    }
+    fn function(&mut self,kind:&str,start:Span)->Result<Stmt, MainError>{
+        let name = self.consume(IDENTIFIER, &format!("Expected {} name.",kind))?;
+        let (params, body) = self.function_body(kind)?;
+        Ok(Stmt::Function(Function::new(name, params, body, self.span_from(start))))
+    }
+    //Parameter list and body, shared by named functions, methods and lambdas
+    fn function_body(&mut self,kind:&str)->Result<(Vec<Token>, Vec<Stmt>), MainError>{
+        self.consume(LeftParen, &format!("Expected \"(\" after {} name.",kind))?;
         let mut params:Vec<Token> = Vec::new();
         if !self.check(RightParen) {
             loop{
                 if params.len() >= 255{
-                    return Err(MainError::ParseError((self.peek().line,"".to_string(),"Can't have more than 255 parameters".to_string())));
+                    return Err(MainError::ParseError((self.peek().span,"".to_string(),"Can't have more than 255 parameters".to_string())));
                 }
-                params.push(self.consume(IDENTIFIER, &format!("Expected a parameter name"))?);
+                params.push(self.consume(IDENTIFIER, "Expected a parameter name")?);
                 if !self.match_(&[COMMA]){
                     break;
                 }
             }
         }
-        self.consume(RightParen, &format!("Expected \")\" after {} name.",kind.to_string()))?;
-        self.consume(LeftBrace, &format!("Expected \")\" after {} name.",kind.to_string()))?;
+        self.consume(RightParen, &format!("Expected \")\" after {} name.",kind))?;
+        self.consume(LeftBrace, &format!("Expected \")\" after {} name.",kind))?;
         let body = self.block_statement()?;
-        Ok(Stmt::Function(Function::new(name, params, body)))
+        Ok((params, body))
     }
     fn var_declaration(&mut self)->Result<Stmt, MainError>{
+        let start = self.previous().span;
         let name = self.consume(IDENTIFIER,"Expected a variable name" )?;
         let initializer=if self.match_(&[EQUAL]){
             Some(self.expression()?)
@@ -97,7 +144,7 @@ impl Parser {
             None
         };
         self.consume(SEMICOLON, "Expected a semicolon after variable declaration")?;
-        Ok(Stmt::Var(Var::new(name, initializer)))
+        Ok(Stmt::Var(Var::new(name, initializer, self.span_from(start))))
     }
     fn statement(&mut self)->Result<Stmt, MainError>{
         // println!("here");
@@ -110,31 +157,48 @@ impl Parser {
         if self.match_(&[WHILE]) {
             return self.while_statement();
         }
+        if self.match_(&[BREAK]) {
+            let keyword = self.previous();
+            self.consume(SEMICOLON, "Expected ';' after 'break'.")?;
+            return Ok(Stmt::Break(Break::new(keyword.clone(), self.span_from(keyword.span))));
+        }
+        if self.match_(&[CONTINUE]) {
+            let keyword = self.previous();
+            self.consume(SEMICOLON, "Expected ';' after 'continue'.")?;
+            return Ok(Stmt::Continue(Continue::new(keyword.clone(), self.span_from(keyword.span))));
+        }
         if self.match_(&[FOR]) {
             return self.for_statement();
         }
         if self.match_(&[IF]) {
             return self.if_statement();
         }
-        if self.match_(&[LeftBrace]) {
+        if !self.starts_map() && self.match_(&[LeftBrace]) {
+            let start = self.previous().span;
             let list = self.block_statement()?;
-            return Ok(Stmt::Block(Block::new(list)));
+            return Ok(Stmt::Block(Block::new(list, self.span_from(start))));
         }
         self.expression_statement()
     }
     fn return_statement(&mut self)->Result<Stmt, MainError>{
         let keyword = self.previous();
-        let mut value = Expr::Literal_(Literal::new(Token::new(NIL,"nil".to_string(),None,keyword.line)));
+        let mut value = Expr::Literal_(Literal::new(self.next_id(),Token::new(NIL,"nil".to_string(),None,keyword.span),keyword.span));
         if !self.check(SEMICOLON){
             value = self.expression()?;
         }
         self.consume(SEMICOLON, "Expected ';' after return value.")?;
-        Ok(Stmt::Return(Return::new(keyword,value)))
+        let span = self.span_from(keyword.span);
+        Ok(Stmt::Return(Return::new(keyword,value,span)))
     }
     fn for_statement(&mut self)->Result<Stmt,MainError>{
         // As for loop is syntactic sugar over while loop i.e. we can express it using while loop
         // We will implement for loop using the technique of desugaring
+        // Every node made up here gets the span of the whole loop
+        let start = self.previous().span;
         self.consume(LeftParen, "Expected '(' after 'for'.")?;
+        if self.check(VAR) && self.check_nth(2, IN) {
+            return self.for_in_statement(start);
+        }
         let initializer = if self.match_(&[SEMICOLON]){
             None
         }else if self.match_(&[VAR]){
@@ -155,30 +219,39 @@ impl Parser {
         };
         self.consume(RightParen, "Expect ')' after for clauses.")?;
         let mut body  = self.statement()?;
-        if let Some(inc) = increment {
-            body = Stmt::Block(Block::new(vec![body,Stmt::Expression(inc)]));
-        }
+        let span = self.span_from(start);
         let condition = if let Some(s) = condition {
             s
         }else{
-            Expr::Literal_(Literal::new(Token::new(TRUE,"true".to_string(),Some(Literals::Boolean(true)),0)))
+            Expr::Literal_(Literal::new(self.next_id(),Token::new(TRUE,"true".to_string(),Some(Literals::Boolean(true)),start),start))
         };
-        body = Stmt::While(While::new(condition, Box::new(body)));
+        body = Stmt::While(While::new(condition, Box::new(body), increment, span));
         body = if let Some(s) = initializer {
-            Stmt::Block(Block::new(vec![s,body]))
+            Stmt::Block(Block::new(vec![s,body], span))
         }else{
             body
         };
         Ok(body)
     }
+    fn for_in_statement(&mut self,start:Span)->Result<Stmt,MainError>{
+        self.consume(VAR, "Expected 'var' in for-in loop.")?;
+        let name = self.consume(IDENTIFIER, "Expected a variable name")?;
+        let keyword = self.consume(IN, "Expected 'in' after the loop variable.")?;
+        let iterable = self.expression()?;
+        self.consume(RightParen, "Expect ')' after for clauses.")?;
+        let body = self.statement()?;
+        Ok(Stmt::ForIn(ForIn::new(name, keyword, iterable, Box::new(body), self.span_from(start))))
+    }
     fn while_statement(&mut self)->Result<Stmt,MainError>{
+        let start = self.previous().span;
         self.consume(LeftParen, "Expected '(' after 'while'.")?;
         let cond = self.expression()?;
         self.consume(RightParen, "Expected ')' after condition.")?;
         let body = self.statement()?;
-        Ok(Stmt::While(While::new(cond,Box::new(body))))
+        Ok(Stmt::While(While::new(cond,Box::new(body),None,self.span_from(start))))
     }
     fn if_statement(&mut self)->Result<Stmt,MainError>{
+        let start = self.previous().span;
         self.consume(LeftParen, "Expected '(' after 'if'.")?;
         let cond = self.expression()?;
         self.consume(RightParen, "Expected ')' after condition.")?;
@@ -188,20 +261,31 @@ impl Parser {
         }else{
             None
         };
-        Ok(Stmt::If(If::new(cond,Box::new(then_branch),else_branch)))
+        Ok(Stmt::If(If::new(cond,Box::new(then_branch),else_branch,self.span_from(start))))
     }
     fn block_statement(&mut self)->Result<Vec<Stmt>,MainError>{
         let mut list:Vec<Stmt> = Vec::new();
         // println!("there");
         while !self.check(RightBrace) && !self.is_at_end(){
             // println!("here");
-            list.push(self.declaration()?);
+            //Errors are recovered from inside the block, so the statements after them are still checked
+            match self.declaration(){
+                Ok(decl)=>list.push(decl),
+                Err(m)=>{
+                    self.record(m);
+                    //The closing brace is left for the block to consume
+                    if !self.check(RightBrace){
+                        self.synchronize();
+                    }
+                }
+            }
         }
         // println!("Is it Right brace :{}",self.peek().lexeme);
         self.consume(RightBrace, "Expected } after the block")?;
         Ok(list)
     }
     fn print_statement(&mut self)->Result<Stmt, MainError>{
+        let start = self.previous().span;
         let mut value = self.expression()?;
         value = if let Expr::Literal_(l) = value{
             // println!("Token of literal {}",l.literal.to_token_string());
@@ -210,30 +294,34 @@ impl Parser {
             value
         };
         self.consume(SEMICOLON, "Expected a ; after value.")?;
-        Ok(Stmt::Print(Print::new(value)))
+        Ok(Stmt::Print(Print::new(value, self.span_from(start))))
     }
     fn expression_statement(&mut self)->Result<Stmt, MainError>{
         let expr = self.expression()?;
         //Adding functionality to REPL such that a simple expression is parsed and its value is displayed immediately
-        match self.consume(SEMICOLON, "Expected a ; after value."){
-            Err(e) =>{
-                if self.repl {
-                    return Ok(Stmt::Print(Print::new(expr)));
-                }else{
-                    return Err(e);
-                }
+        if let Err(e) = self.consume(SEMICOLON, "Expected a ; after value.") {
+            if self.repl {
+                let span = expr.span();
+                return Ok(Stmt::Print(Print::new(expr, span)));
+            }else{
+                return Err(e);
             }
-            Ok(_) =>(),
         }
         Ok(Stmt::Expression(expr))
     }
+    //Span from `start` up to the end of the last consumed token
+    fn span_from(&self,start:Span)->Span{
+        start.to(self.previous().span)
+    }
+    fn next_id(&self)->usize{
+        NEXT_EXPR_ID.fetch_add(1, Ordering::Relaxed)
+    }
     fn peek(&self)->Token{
         self.tokens[self.current].clone()
     }
     fn previous(&self)->Token{
         self.tokens[self.current-1].clone()
     }
-    #[allow(dead_code)]
     fn synchronize(&mut self){
         self.advance();
         while !self.is_at_end() {
@@ -241,7 +329,7 @@ impl Parser {
                 break;
             }
             match self.peek().type_{
-                CLASS|FUN|VAR|FOR|IF|WHILE|PRINT|RETURN => break,
+                CLASS|FUN|VAR|FOR|IF|WHILE|PRINT|RETURN|BREAK|CONTINUE|RightBrace => break,
                 _ => {self.advance();},
             }
         }
@@ -255,6 +343,20 @@ impl Parser {
         }
         self.previous()
     }
+    fn check_next(&self,t:TokenType)->bool{
+        match self.tokens.get(self.current+1){
+            Some(token) => token.type_ == t,
+            None => false,
+        }
+    }
+    //Whether the `{` at the start of a statement opens a map rather than a block, i.e. a key and a colon follow it
+    fn starts_map(&self)->bool{
+        self.check(LeftBrace) && self.check_nth(2, COLON)
+    }
+    //Checks the token `n` places ahead of the current one
+    fn check_nth(&self,n:usize,t:TokenType)->bool{
+        matches!(self.tokens.get(self.current+n), Some(token) if token.type_ == t)
+    }
     fn check(&self,t:TokenType)->bool{
         if self.is_at_end(){
             false
@@ -281,13 +383,16 @@ impl Parser {
         if self.match_(&[EQUAL]){
             let equals = self.previous();
             let value = self.assignment()?;
+            let span = expr.span().to(value.span());
             if let Expr::Variable(v) = expr{
                 let name = v.var;
-                return Ok(Expr::Assign(Assign::new(name,Box::new(value))));
+                return Ok(Expr::Assign(Assign::new(self.next_id(),name,Box::new(value),span)));
             }else if let Expr::Get(g) = expr {
-                return Ok(Expr::Set(Set::new(g.object,g.name,Box::new(value))));
+                return Ok(Expr::Set(Set::new(self.next_id(),g.object,g.name,Box::new(value),span)));
+            }else if let Expr::Index(i) = expr {
+                return Ok(Expr::IndexSet(IndexSet::new(self.next_id(),i.object,i.bracket,i.index,Box::new(value),span)));
             }else{
-                return Err(MainError::ParseError((equals.line,equals.lexeme,"Invalid Assignment Target".to_string())));
+                return Err(MainError::ParseError((equals.span,equals.lexeme,"Invalid Assignment Target".to_string())));
             }
         }
         Ok(expr)
@@ -297,7 +402,8 @@ impl Parser {
         while self.match_(&[OR]){
             let operator = self.previous();
             let right = self.and()?;
-            expr = Expr::Logical(Logical::new(Box::new(expr),operator,Box::new(right)));
+            let span = expr.span().to(right.span());
+            expr = Expr::Logical(Logical::new(self.next_id(),Box::new(expr),operator,Box::new(right),span));
         }
         Ok(expr)
     }
@@ -306,7 +412,8 @@ impl Parser {
         while self.match_(&[AND]){
             let operator = self.previous();
             let right = self.equality()?;
-            expr = Expr::Logical(Logical::new(Box::new(expr),operator,Box::new(right)));
+            let span = expr.span().to(right.span());
+            expr = Expr::Logical(Logical::new(self.next_id(),Box::new(expr),operator,Box::new(right),span));
         }
         Ok(expr)
     }
@@ -315,7 +422,8 @@ impl Parser {
         while self.match_(&[TokenType::EqualEqual,TokenType::BangEqual]) {
             let operator = self.previous();
             let right = self.comparison()?;
-            expr = Expr::Binary(Binary::new(Box::new(expr.clone()),operator,Box::new(right.clone())));
+            let span = expr.span().to(right.span());
+            expr = Expr::Binary(Binary::new(self.next_id(),Box::new(expr),operator,Box::new(right),span));
         }
         Ok(expr)
     }
@@ -324,7 +432,8 @@ impl Parser {
         while self.match_(&[GREATER, GreaterEqual, LESS, LessEqual]) {
             let operator = self.previous();
             let right = self.term()?;
-            expr = Expr::Binary(Binary::new(Box::new(expr.clone()),operator,Box::new(right.clone())));
+            let span = expr.span().to(right.span());
+            expr = Expr::Binary(Binary::new(self.next_id(),Box::new(expr),operator,Box::new(right),span));
         }
         Ok(expr)
     }
@@ -333,7 +442,8 @@ impl Parser {
         while self.match_(&[PLUS,MINUS]) {
             let operator = self.previous();
             let right = self.factor()?;
-            expr = Expr::Binary(Binary::new(Box::new(expr.clone()),operator,Box::new(right.clone())));
+            let span = expr.span().to(right.span());
+            expr = Expr::Binary(Binary::new(self.next_id(),Box::new(expr),operator,Box::new(right),span));
 
         }
         Ok(expr)
@@ -343,7 +453,8 @@ impl Parser {
         while self.match_(&[SLASH,STAR]) {
             let operator = self.previous();
             let right = self.unary()?;
-            expr = Expr::Binary(Binary::new(Box::new(expr.clone()),operator,Box::new(right.clone())));
+            let span = expr.span().to(right.span());
+            expr = Expr::Binary(Binary::new(self.next_id(),Box::new(expr),operator,Box::new(right),span));
         }
         Ok(expr)
     }
@@ -351,7 +462,8 @@ impl Parser {
         if self.match_(&[BANG, MINUS]){
             let operator = self.previous();
             let right = self.unary()?;
-            Ok(Expr::Unary(Unary::new(operator,Box::new(right.clone()))))
+            let span = operator.span.to(right.span());
+            Ok(Expr::Unary(Unary::new(self.next_id(),operator,Box::new(right),span)))
         }else{
             self.call()
         }
@@ -363,7 +475,13 @@ impl Parser {
                 expr = self.finish_call(expr)?;
             }else if self.match_(&[DOT]){
                 let name = self.consume(IDENTIFIER, "Expected a property name after '.'.")?;
-                expr = Expr::Get(Get::new(Box::new(expr), name));
+                let span = expr.span().to(name.span);
+                expr = Expr::Get(Get::new(self.next_id(),Box::new(expr), name, span));
+            }else if self.match_(&[LeftBracket]){
+                let index = self.expression()?;
+                let bracket = self.consume(RightBracket, "Expected a ] after index.")?;
+                let span = expr.span().to(bracket.span);
+                expr = Expr::Index(Index::new(self.next_id(),Box::new(expr),bracket,Box::new(index),span));
             }else{
                 break;
             }
@@ -374,8 +492,8 @@ impl Parser {
         let mut arguments:Vec<Expr> = Vec::new();
         if !self.check(RightParen) {
             loop{
-                if arguments.len() >= 2 {
-                    return Err(MainError::ParseError((self.peek().line,"".to_string(),"Can't have more than 255 arguments".to_string())));
+                if arguments.len() >= 255 {
+                    return Err(MainError::ParseError((self.peek().span,"".to_string(),"Can't have more than 255 arguments".to_string())));
                 }
                 arguments.push(self.expression()?);
                 if !self.match_(&[COMMA]){
@@ -384,29 +502,75 @@ impl Parser {
             }
         }
         let paren = self.consume(RightParen, "Expected a ) after argumetns")?;
-        Ok(Expr::Call(Call::new(Box::new(callee),paren,arguments)))
+        let span = callee.span().to(paren.span);
+        Ok(Expr::Call(Call::new(self.next_id(),Box::new(callee),paren,arguments,span)))
     }
     fn primary(&mut self)->Result<Expr,MainError>{
         if self.match_(&[FALSE, TRUE, NIL, NUMBER, STRING]){
             // println!("bool detecged");
-            Ok(Expr::Literal_(Literal::new(self.previous())))
+            let literal = self.previous();
+            let span = literal.span;
+            Ok(Expr::Literal_(Literal::new(self.next_id(),literal,span)))
         }else if self.match_(&[IDENTIFIER]){
-            Ok(Expr::Variable(Variable::new(self.previous())))
+            let var = self.previous();
+            let span = var.span;
+            Ok(Expr::Variable(Variable::new(self.next_id(),var,span)))
         }else if self.match_(&[SUPER]){
             let keyword = self.previous();
             self.consume(DOT, "Expected '.' after 'super'.")?;
             let method = self.consume(IDENTIFIER, "Expected '.' after 'super'.")?;
-            Ok(Expr::Super(Super::new(keyword,method)))
+            let span = keyword.span.to(method.span);
+            Ok(Expr::Super(Super::new(self.next_id(),keyword,method,span)))
         }else if self.match_(&[THIS]){
-            Ok(Expr::This(This::new(self.previous())))
+            let keyword = self.previous();
+            let span = keyword.span;
+            Ok(Expr::This(This::new(self.next_id(),keyword,span)))
+        }else if self.match_(&[FUN]){
+            let keyword = self.previous();
+            let (params, body) = self.function_body("lambda")?;
+            let name = Token::new(IDENTIFIER, "lambda".to_string(), None, keyword.span);
+            let span = self.span_from(keyword.span);
+            Ok(Expr::Lambda(Lambda::new(self.next_id(),keyword, Rc::new(Function::new(name, params, body, span)), span)))
+        }else if self.match_(&[LeftBracket]){
+            let bracket = self.previous();
+            let mut elements = Vec::new();
+            if !self.check(RightBracket) {
+                loop{
+                    elements.push(self.expression()?);
+                    if !self.match_(&[COMMA]){
+                        break;
+                    }
+                }
+            }
+            self.consume(RightBracket, "Expected a ] after list elements.")?;
+            let span = self.span_from(bracket.span);
+            Ok(Expr::ListLiteral(ListLiteral::new(self.next_id(),bracket,elements,span)))
+        }else if self.match_(&[LeftBrace]){
+            let brace = self.previous();
+            let mut entries = Vec::new();
+            if !self.check(RightBrace) {
+                loop{
+                    let key = self.expression()?;
+                    self.consume(COLON, "Expected a : after map key.")?;
+                    let value = self.expression()?;
+                    entries.push((key, value));
+                    if !self.match_(&[COMMA]){
+                        break;
+                    }
+                }
+            }
+            self.consume(RightBrace, "Expected a } after map entries.")?;
+            let span = self.span_from(brace.span);
+            Ok(Expr::MapLiteral(MapLiteral::new(self.next_id(),brace,entries,span)))
         }else if self.match_(&[LeftParen]){
+            let start = self.previous().span;
             let expr = self.expression()?;
             // let mut temp = ASTprinter;
             // println!("{}",temp.print(&mut expr));
             self.consume(RightParen,"Expect ')' after expression.")?;
-            Ok(Expr::Grouping(Grouping::new(Box::new(expr.clone()))))
+            Ok(Expr::Grouping(Grouping::new(self.next_id(),Box::new(expr),self.span_from(start))))
         }else{
-            Err(MainError::ParseError((self.peek().line,"at ".to_owned()+&self.peek().lexeme,"Expected: NUMBER | STRING | \"true\" | \"false\" | \"nil\" | \"(\" Found Something else".to_string())))
+            Err(MainError::ParseError((self.peek().span,"at ".to_owned()+&self.peek().lexeme,"Expected: NUMBER | STRING | \"true\" | \"false\" | \"nil\" | \"(\" | \"[\" | \"{\" Found Something else".to_string())))
         }
     }
     fn consume(&mut self,t:TokenType,s:&str)->Result<Token,MainError>{
@@ -414,9 +578,9 @@ impl Parser {
             Ok(self.advance())
         }else{
             let parse_error = if self.peek().type_ == EOF{
-                MainError::ScanningError((self.peek().line,"at end".to_string(),s.to_string()))
+                MainError::ParseError((self.peek().span,"at end".to_string(),s.to_string()))
             }else{
-                MainError::ScanningError((self.peek().line,"at ".to_owned()+&self.peek().lexeme,s.to_string()))
+                MainError::ParseError((self.peek().span,"at ".to_owned()+&self.peek().lexeme,s.to_string()))
             };
             Err(parse_error)
         }
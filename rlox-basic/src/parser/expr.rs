@@ -1,5 +1,7 @@
 //!Implementing visitor pattern
 use super::super::tokens::*;
+use super::stmts::Function;
+use std::rc::Rc;
 pub trait VisitorExpr<R>{
     fn visit_binary_exp(&mut self,expr:&mut Binary)->R;
     fn visit_literal_exp(&mut self,expr:&mut Literal)->R;
@@ -13,11 +15,16 @@ pub trait VisitorExpr<R>{
     fn visit_set_exp(&mut self,expr:&mut Set)->R;
     fn visit_this_exp(&mut self,expr:&mut This)->R;
     fn visit_super_exp(&mut self,expr:&mut Super)->R;
+    fn visit_lambda_exp(&mut self,expr:&mut Lambda)->R;
+    fn visit_list_exp(&mut self,expr:&mut ListLiteral)->R;
+    fn visit_index_exp(&mut self,expr:&mut Index)->R;
+    fn visit_index_set_exp(&mut self,expr:&mut IndexSet)->R;
+    fn visit_map_exp(&mut self,expr:&mut MapLiteral)->R;
 }
 
 
 ///Expressions
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum Expr{
     Literal_(Literal),
     Binary(Binary),
@@ -30,9 +37,58 @@ pub enum Expr{
     Get(Get),
     Set(Set),
     This(This),
-    Super(Super)
+    Super(Super),
+    Lambda(Lambda),
+    ListLiteral(ListLiteral),
+    Index(Index),
+    IndexSet(IndexSet),
+    MapLiteral(MapLiteral)
 }
 impl Expr{
+    //Unique id given by the parser, the resolver uses it to record where a variable lives
+    pub fn id(&self)->usize{
+        match self{
+            Self::Binary(b) => b.id,
+            Self::Grouping(g) => g.id,
+            Self::Literal_(l) => l.id,
+            Self::Unary(u) => u.id,
+            Self::Variable(v) => v.id,
+            Self::Assign(a) => a.id,
+            Self::Logical(l) => l.id,
+            Self::Call(c) => c.id,
+            Self::Get(g) => g.id,
+            Self::Set(s) => s.id,
+            Self::This(t) => t.id,
+            Self::Super(s) => s.id,
+            Self::Lambda(l) => l.id,
+            Self::ListLiteral(l) => l.id,
+            Self::Index(i) => i.id,
+            Self::IndexSet(i) => i.id,
+            Self::MapLiteral(m) => m.id,
+        }
+    }
+    //Source range covered by the whole expression
+    pub fn span(&self)->Span{
+        match self{
+            Self::Binary(b) => b.span,
+            Self::Grouping(g) => g.span,
+            Self::Literal_(l) => l.span,
+            Self::Unary(u) => u.span,
+            Self::Variable(v) => v.span,
+            Self::Assign(a) => a.span,
+            Self::Logical(l) => l.span,
+            Self::Call(c) => c.span,
+            Self::Get(g) => g.span,
+            Self::Set(s) => s.span,
+            Self::This(t) => t.span,
+            Self::Super(s) => s.span,
+            Self::Lambda(l) => l.span,
+            Self::ListLiteral(l) => l.span,
+            Self::Index(i) => i.span,
+            Self::IndexSet(i) => i.span,
+            Self::MapLiteral(m) => m.span,
+        }
+    }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         match self{
             Self::Binary(b) => b.accept(visitor),
@@ -47,140 +103,280 @@ impl Expr{
             Self::Set(s) => s.accept(visitor),
             Self::This(t) => t.accept(visitor),
             Self::Super(s) => s.accept(visitor),
+            Self::Lambda(l) => l.accept(visitor),
+            Self::ListLiteral(l) => l.accept(visitor),
+            Self::Index(i) => i.accept(visitor),
+            Self::IndexSet(i) => i.accept(visitor),
+            Self::MapLiteral(m) => m.accept(visitor),
         }
     }
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+//List written out element by element, e.g. `[1, 2, 3]`
+#[derive(Clone, Debug)]
+pub struct ListLiteral{
+    pub id:usize,
+    pub bracket:Token,
+    pub elements:Vec<Expr>,
+    pub span:Span,
+}
+impl ListLiteral{
+    pub fn new(id:usize,bracket:Token,elements:Vec<Expr>,span:Span)->ListLiteral{
+        ListLiteral { id, bracket, elements, span }
+    }
+    pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
+        visitor.visit_list_exp(self)
+    }
+}
+//Map written out entry by entry, e.g. `{"a": 1, "b": 2}`, keys are kept in the order written
+#[derive(Clone, Debug)]
+pub struct MapLiteral{
+    pub id:usize,
+    pub brace:Token,
+    pub entries:Vec<(Expr,Expr)>,
+    pub span:Span,
+}
+impl MapLiteral{
+    pub fn new(id:usize,brace:Token,entries:Vec<(Expr,Expr)>,span:Span)->MapLiteral{
+        MapLiteral { id, brace, entries, span }
+    }
+    pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
+        visitor.visit_map_exp(self)
+    }
+}
+//Reading an element, e.g. `xs[i]`, the closing bracket is kept for error reporting
+#[derive(Clone, Debug)]
+pub struct Index{
+    pub id:usize,
+    pub object:Box<Expr>,
+    pub bracket:Token,
+    pub index:Box<Expr>,
+    pub span:Span,
+}
+impl Index{
+    pub fn new(
+        id:usize,
+        object:Box<Expr>,
+        bracket:Token,
+        index:Box<Expr>,
+        span:Span
+    )->Index{
+        Index { id, object, bracket, index, span }
+    }
+    pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
+        visitor.visit_index_exp(self)
+    }
+}
+//Writing an element, e.g. `xs[i] = v`
+#[derive(Clone, Debug)]
+pub struct IndexSet{
+    pub id:usize,
+    pub object:Box<Expr>,
+    pub bracket:Token,
+    pub index:Box<Expr>,
+    pub value:Box<Expr>,
+    pub span:Span,
+}
+impl IndexSet{
+    pub fn new(
+        id:usize,
+        object:Box<Expr>,
+        bracket:Token,
+        index:Box<Expr>,
+        value:Box<Expr>,
+        span:Span
+    )->IndexSet{
+        IndexSet { id, object, bracket, index, value, span }
+    }
+    pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
+        visitor.visit_index_set_exp(self)
+    }
+}
+//Anonymous function used as an expression, e.g. `fun (a, b) { return a + b; }`
+#[derive(Clone, Debug)]
+pub struct Lambda{
+    pub id:usize,
+    pub keyword:Token,
+    pub declaration:Rc<Function>,
+    pub span:Span,
+}
+impl Lambda{
+    pub fn new(
+        id:usize,
+        keyword:Token,
+        declaration:Rc<Function>,
+        span:Span
+    )->Lambda{
+        Lambda { id, keyword, declaration, span }
+    }
+    pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
+        visitor.visit_lambda_exp(self)
+    }
+}
+#[derive(Clone, Debug)]
 pub struct Super{
+    pub id:usize,
     pub keyword:Token,
-    pub method:Token
+    pub method:Token,
+    pub span:Span,
 }
 impl Super{
     pub fn new(
+        id:usize,
         keyword:Token,
-        method:Token
+        method:Token,
+        span:Span
     )->Super{
-        Super { keyword, method }
+        Super { id, keyword, method, span }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         visitor.visit_super_exp(self)
     }
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct This{
-    pub keyword:Token
+    pub id:usize,
+    pub keyword:Token,
+    pub span:Span,
 }
 impl This{
-    pub fn new(keyword:Token)->This{
-        This { keyword }
+    pub fn new(id:usize,keyword:Token,span:Span)->This{
+        This { id, keyword, span }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         visitor.visit_this_exp(self)
     }
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Set{
+    pub id:usize,
     pub object:Box<Expr>,
     pub name:Token,
     pub value:Box<Expr>,
+    pub span:Span,
 }
 impl Set{
     pub fn new(
+        id:usize,
         object:Box<Expr>,
         name:Token,
         value:Box<Expr>,
+        span:Span
     )->Set{
-        Set { object, name, value}
+        Set { id, object, name, value, span }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         visitor.visit_set_exp(self)
     }
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Get{
+    pub id:usize,
     pub object:Box<Expr>,
-    pub name:Token
+    pub name:Token,
+    pub span:Span,
 }
 impl Get{
     pub fn new(
+        id:usize,
         object:Box<Expr>,
-        name:Token
+        name:Token,
+        span:Span
     )->Get{
-        Get { object, name }
+        Get { id, object, name, span }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         visitor.visit_get_exp(self)
     }
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 //We’ll use that token’s location when we report a runtime error caused by a function call.
 pub struct Call{
+    pub id:usize,
     pub callee:Box<Expr>,
     pub paren:Token,
-    pub arguments:Vec<Expr>
+    pub arguments:Vec<Expr>,
+    pub span:Span,
 }
 impl Call{
     pub fn new(
+        id:usize,
         callee:Box<Expr>,
         paren:Token,
-        arguments:Vec<Expr>
+        arguments:Vec<Expr>,
+        span:Span
     )->Call{
-        Call { callee, paren, arguments }
+        Call { id, callee, paren, arguments, span }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         visitor.visit_call_exp(self)
     }
      
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Logical{
+    pub id:usize,
     pub left:Box<Expr>,
     pub operator:Token,
-    pub right:Box<Expr>
+    pub right:Box<Expr>,
+    pub span:Span,
 }
 impl Logical{
     pub fn new(
+        id:usize,
         left:Box<Expr>,
         operator:Token,
-        right:Box<Expr>
+        right:Box<Expr>,
+        span:Span
     )->Logical{
         Logical{
+            id,
             left,
             operator,
-            right
+            right,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         visitor.visit_logical_exp(self)
     }
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Assign{
+    pub id:usize,
     pub name:Token,
     pub value:Box<Expr>,
+    pub span:Span,
 }
 impl Assign{
     pub fn new(
+        id:usize,
         name:Token,
-        value:Box<Expr>
+        value:Box<Expr>,
+        span:Span
     )->Assign{
         Assign{
+            id,
             name,
             value,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         visitor.visit_assign_exp(self)
     } 
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Literal{
+    pub id:usize,
     pub literal:Token,
+    pub span:Span,
 }
 impl Literal{
-    pub fn new(literal:Token)->Self{
+    pub fn new(id:usize,literal:Token,span:Span)->Self{
         Literal{
-            literal
+            id,
+            literal,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
@@ -188,40 +384,51 @@ impl Literal{
         visitor.visit_literal_exp(self)
     }  
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Binary{
+    pub id:usize,
     pub left:Box<Expr>,
     pub operator:Token,
     pub right:Box<Expr>,
+    pub span:Span,
 }
 impl Binary{
-    pub fn new(left:Box<Expr>,
+    pub fn new(id:usize,left:Box<Expr>,
         operator:Token,
-        right:Box<Expr>
+        right:Box<Expr>,
+        span:Span
     )->Self{
         Binary{
+            id,
             left,
             operator,
-            right
+            right,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         visitor.visit_binary_exp(self)
     }
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Unary{
+    pub id:usize,
     pub operator:Token,
-    pub expr:Box<Expr>
+    pub expr:Box<Expr>,
+    pub span:Span,
 }
 impl Unary{
     pub fn new(
+        id:usize,
         operator:Token,
-        expr:Box<Expr>
+        expr:Box<Expr>,
+        span:Span
     )->Self{
         Unary{
+            id,
             operator,
-            expr
+            expr,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
@@ -229,28 +436,36 @@ impl Unary{
     }
 }
 
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Grouping{
+    pub id:usize,
     pub expr:Box<Expr>,
+    pub span:Span,
 }
 impl Grouping{
-    pub fn new(expr:Box<Expr>)->Self{
+    pub fn new(id:usize,expr:Box<Expr>,span:Span)->Self{
         Grouping{
-            expr
+            id,
+            expr,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
         visitor.visit_grouping_exp(self)
     }
 }
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Variable{
+    pub id:usize,
     pub var:Token,
+    pub span:Span,
 }
 impl Variable{
-    pub fn new(var:Token)->Variable{
+    pub fn new(id:usize,var:Token,span:Span)->Variable{
         Variable{
+            id,
             var,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorExpr<R>)->R{
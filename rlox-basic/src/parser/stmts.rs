@@ -1,14 +1,18 @@
 use super::super::tokens::*;
 use crate::parser::expr::*;
 pub trait VisitorStmt<R>{
+    fn visit_expression_stmt(&mut self,expr:&mut Expr)->R;
     fn visit_print_stmt(&mut self,stmt:&mut Print)->R;
     fn visit_variable_stmt(&mut self,stmt:&mut Var)->R;
     fn visit_block_stmt(&mut self,stmt:&mut Block)->R;
     fn visit_if_stmt(&mut self,stmt:&mut If)->R;
     fn visit_while_stmt(&mut self,stmt:&mut While)->R;
+    fn visit_for_in_stmt(&mut self,stmt:&mut ForIn)->R;
     fn visit_fn_stmt(&mut self,stmt:&mut Function)->R;
     fn visit_return_stmt(&mut self,stmt:&mut Return)->R;
     fn visit_class_stmt(&mut self,stmt:&mut Class)->R;
+    fn visit_break_stmt(&mut self,stmt:&mut Break)->R;
+    fn visit_continue_stmt(&mut self,stmt:&mut Continue)->R;
 }
 ///Statements
 #[derive(Clone, Debug)]
@@ -19,27 +23,47 @@ pub enum Stmt{
     Block(Block),
     If(If),
     While(While),
+    ForIn(ForIn),
     Function(Function),
     Return(Return),
-    Class(Class)
+    Class(Class),
+    Break(Break),
+    Continue(Continue)
 }
 
 impl Stmt{
-    pub fn accept<R,T>(&mut self,visitor:&mut T)->R
-    where
-        T:VisitorExpr<R>+VisitorStmt<R>,
-    {
-
+    //Source range covered by the whole statement
+    pub fn span(&self)->Span{
+        match self{
+            Self::Expression(e)=>e.span(),
+            Self::Print(p)=>p.span,
+            Self::Var(v) => v.span,
+            Self::Block(b) => b.span,
+            Self::If(i) => i.span,
+            Self::While(w) => w.span,
+            Self::ForIn(f) => f.span,
+            Self::Function(f) => f.span,
+            Self::Return(r) => r.span,
+            Self::Class(c) => c.span,
+            Self::Break(b) => b.span,
+            Self::Continue(c) => c.span
+        }
+    }
+    //Statements have their own result type, so an expression statement is handed to the statement visitor as a whole
+    pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
         match self{
-            Self::Expression(e)=>e.accept(visitor),
+            Self::Expression(e)=>visitor.visit_expression_stmt(e),
             Self::Print(p)=>p.accept(visitor),
             Self::Var(v) => v.accept(visitor),
             Self::Block(b) => b.accept(visitor),
             Self::If(i) => i.accept(visitor),
             Self::While(w) => w.accept(visitor),
+            Self::ForIn(f) => f.accept(visitor),
             Self::Function(f) => f.accept(visitor),
             Self::Return(r) => r.accept(visitor),
-            Self::Class(c) => c.accept(visitor)
+            Self::Class(c) => c.accept(visitor),
+            Self::Break(b) => b.accept(visitor),
+            Self::Continue(c) => c.accept(visitor)
         }
     }
 }
@@ -47,15 +71,20 @@ impl Stmt{
 pub struct Class{
     pub name:Token,
     pub methods:Vec<Function>,
-    pub superclass:Option<Variable>
+    //Methods declared with a leading `class`, they are called on the class object itself
+    pub static_methods:Vec<Function>,
+    pub superclass:Option<Variable>,
+    pub span:Span,
 }
 impl Class{
     pub fn new(
         name:Token,
         methods:Vec<Function>,
-        superclass:Option<Variable>
+        static_methods:Vec<Function>,
+        superclass:Option<Variable>,
+        span:Span
     )->Class{
-        Class { name, methods, superclass }
+        Class { name, methods, static_methods, superclass, span }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
         visitor.visit_class_stmt(self)
@@ -65,37 +94,87 @@ impl Class{
 pub struct Function{
     pub name:Token,
     pub params:Vec<Token>,
-    pub body:Vec<Stmt>
+    pub body:Vec<Stmt>,
+    //Getters are declared without a parameter list and run as soon as the property is accessed
+    pub is_getter:bool,
+    pub span:Span,
 }
 impl Function{
     pub fn new(
         name:Token,
         params:Vec<Token>,
-        body:Vec<Stmt>
+        body:Vec<Stmt>,
+        span:Span
     )->Function{
         Function{
             name,
             params,
-            body
+            body,
+            is_getter:false,
+            span
+        }
+    }
+    pub fn getter(
+        name:Token,
+        body:Vec<Stmt>,
+        span:Span
+    )->Function{
+        Function{
+            name,
+            params:Vec::new(),
+            body,
+            is_getter:true,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
         visitor.visit_fn_stmt(self)
     }
 }
+//`for (var name in iterable) body`, every iteration gets its own scope holding the loop variable
+#[derive(Clone, Debug)]
+pub struct ForIn{
+    pub name:Token,
+    //The `in` keyword, calls made to drive an iterator object are reported at it
+    pub keyword:Token,
+    pub iterable:Expr,
+    pub body:Box<Stmt>,
+    pub span:Span,
+}
+impl ForIn{
+    pub fn new(
+        name:Token,
+        keyword:Token,
+        iterable:Expr,
+        body:Box<Stmt>,
+        span:Span
+    )->ForIn{
+        ForIn { name, keyword, iterable, body, span }
+    }
+    pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
+        visitor.visit_for_in_stmt(self)
+    }
+}
 #[derive(Clone, Debug)]
 pub struct While{
     pub condition:Expr,
     pub body:Box<Stmt>,
+    //Increment of a desugared for loop, kept apart from the body so that `continue` still runs it
+    pub increment:Option<Expr>,
+    pub span:Span,
 }
 impl While{
     pub fn new(
         condition:Expr,
         body:Box<Stmt>,
+        increment:Option<Expr>,
+        span:Span
     )->While{
         While{
             condition,
             body,
+            increment,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
@@ -106,18 +185,21 @@ impl While{
 pub struct If{
     pub condition:Expr,
     pub then_branch:Box<Stmt>,
-    pub else_branch:Option<Box<Stmt>>
+    pub else_branch:Option<Box<Stmt>>,
+    pub span:Span,
 }
 impl If{
     pub fn new(
         condition:Expr,
         then_branch:Box<Stmt>,
-        else_branch:Option<Box<Stmt>>
+        else_branch:Option<Box<Stmt>>,
+        span:Span
     )->If{
         If{
             condition,
             then_branch,
-            else_branch
+            else_branch,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
@@ -127,12 +209,14 @@ impl If{
 #[derive(Clone, Debug)]
 pub struct Print{
     pub expr:Expr,
+    pub span:Span,
 }
 
 impl Print{
-    pub fn new(expr:Expr)->Print{
+    pub fn new(expr:Expr,span:Span)->Print{
         Print{
             expr,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
@@ -143,15 +227,18 @@ impl Print{
 pub struct Var{
     pub name:Token,
     pub initializer:Option<Expr>,
+    pub span:Span,
 }
 impl Var{
     pub fn new(
         name:Token,
-        initializer:Option<Expr>
+        initializer:Option<Expr>,
+        span:Span
     )->Var{
         Var{
             name,
-            initializer
+            initializer,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
@@ -160,12 +247,14 @@ impl Var{
 }
 #[derive(Clone, Debug)]
 pub struct Block{
-    pub list:Vec<Stmt>
+    pub list:Vec<Stmt>,
+    pub span:Span,
 }
 impl Block{
-    pub fn new(list:Vec<Stmt>)->Block{
+    pub fn new(list:Vec<Stmt>,span:Span)->Block{
         Block{
-            list
+            list,
+            span
         }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
@@ -175,17 +264,45 @@ impl Block{
 #[derive(Clone, Debug)]
 pub struct Return{
     pub keyword:Token,
-    pub value:Expr
+    pub value:Expr,
+    pub span:Span,
 }
 
 impl Return{
     pub fn new(
         keyword:Token,
-        value:Expr
+        value:Expr,
+        span:Span
     )->Return{
-        Return { keyword, value}
+        Return { keyword, value, span }
     }
     pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
         visitor.visit_return_stmt(self)
     }
 }
+#[derive(Clone, Debug)]
+pub struct Break{
+    pub keyword:Token,
+    pub span:Span,
+}
+impl Break{
+    pub fn new(keyword:Token,span:Span)->Break{
+        Break { keyword, span }
+    }
+    pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
+        visitor.visit_break_stmt(self)
+    }
+}
+#[derive(Clone, Debug)]
+pub struct Continue{
+    pub keyword:Token,
+    pub span:Span,
+}
+impl Continue{
+    pub fn new(keyword:Token,span:Span)->Continue{
+        Continue { keyword, span }
+    }
+    pub fn accept<R>(&mut self,visitor:&mut dyn VisitorStmt<R>)->R{
+        visitor.visit_continue_stmt(self)
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use crate::MainError;
+use crate::diagnostics::Diagnostic;
 use crate::parser::stmts::VisitorStmt;
 use crate::tokens::Token;
 use crate::{interpretor::Interpretor, parser::expr::VisitorExpr};
@@ -8,28 +9,54 @@ use crate::stmts::*;
 use crate::tokens::TokenType;
 pub struct Resolver<'a>{
     interpreter:&'a mut Interpretor,
-    pub scopes:Vec<HashMap<String,bool>>,
+    pub scopes:Vec<HashMap<String,Local>>,
     current_function:FunctionType,
-    current_class:ClassType
+    current_class:ClassType,
+    //Number of loops enclosing the code being resolved, reset at function boundaries
+    loop_depth:usize,
+    //Errors found so far, each statement list carries on after a statement that failed
+    errors:Vec<Diagnostic>
 }
 impl<'a> Resolver<'a>{
-    pub fn new(interpreter:&mut Interpretor)->Resolver{
+    pub fn new(interpreter:&mut Interpretor)->Resolver<'_>{
         Resolver { 
             interpreter, scopes:Vec::new() ,  current_function:FunctionType::None,
-            current_class:ClassType::None
+            current_class:ClassType::None, loop_depth:0, errors:Vec::new()
         }
     }
-    pub fn resolve(&mut self,statements:Vec<Stmt>)->Result<(),MainError>{
-        for mut ele in statements{
-            ele.accept(self)?;
+    //Resolves code as if it was written inside the given scopes, outermost first, e.g. the scopes of a paused program
+    pub fn with_scopes(interpreter:&mut Interpretor,scopes:Vec<Vec<String>>)->Resolver<'_>{
+        let mut resolver = Resolver::new(interpreter);
+        for names in scopes{
+            if names.iter().any(|n| n == "super"){
+                resolver.current_class = ClassType::SubClass;
+            }else if names.iter().any(|n| n == "this") && resolver.current_class == ClassType::None{
+                resolver.current_class = ClassType::Class;
+            }
+            resolver.scopes.push(names.into_iter().enumerate().map(|(slot,name)| (name,Local{ defined:true, slot })).collect());
+        }
+        resolver
+    }
+    pub fn resolve(&mut self,statements:&mut [Stmt])->Result<(),Vec<Diagnostic>>{
+        self.resolve_statements(statements);
+        if self.errors.is_empty(){
+            Ok(())
+        }else{
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+    fn resolve_statements(&mut self,statements:&mut [Stmt]){
+        for ele in statements{
+            if let Err(e) = ele.accept(self){
+                self.errors.push(Diagnostic::from(e));
+            }
         }
-        Ok(())
     }
-    fn resolve_stmt(&mut self,mut statement:Stmt)->Result<(),MainError>{
+    fn resolve_stmt(&mut self,statement:&mut Stmt)->Result<(),MainError>{
         statement.accept(self)?;
         Ok(())
     }
-    fn resolve_expr(&mut self,mut expression:Expr)->Result<(),MainError>{
+    fn resolve_expr(&mut self,expression:&mut Expr)->Result<(),MainError>{
         expression.accept(self)?;
         Ok(())
     }
@@ -47,14 +74,15 @@ impl<'a> Resolver<'a>{
         let scope = if let Some(s)= scope {
             s
         }else{
-            return Err(MainError::ResolvingError((name.line,name.lexeme,"No scope found 1".to_string())));
+            return Err(MainError::ResolvingError((name.span,name.lexeme,"No scope found 1".to_string())));
         };
-        if scope.contains_key(&name.lexeme) && scope.get(&name.lexeme) == Some(&true){
+        if scope.get(&name.lexeme).map(|l| l.defined) == Some(true){
             // panic!("fail");
-            return Err(MainError::ResolvingError((name.line,name.lexeme,"Already a variable with this name".to_string())));
+            return Err(MainError::ResolvingError((name.span,name.lexeme,"Already a variable with this name".to_string())));
         }
         // print!("pass");
-        (*scope).insert(name.lexeme, false);
+        let slot = scope.len();
+        scope.entry(name.lexeme).or_insert(Local{ defined:false, slot });
         Ok(())
     }
     fn define(&mut self,name:Token)->Result<(),MainError>{
@@ -65,125 +93,165 @@ impl<'a> Resolver<'a>{
         let scope = if let Some(s)= scope {
             s
         }else{
-            return Err(MainError::ResolvingError((name.line,name.lexeme,"No scope found 2".to_string())));
+            return Err(MainError::ResolvingError((name.span,name.lexeme,"No scope found 2".to_string())));
         };
-        if scope.contains_key(&name.lexeme) && scope.get(&name.lexeme) == Some(&true){
+        if scope.get(&name.lexeme).map(|l| l.defined) == Some(true){
             // dbg!(self.scopes.clone());
             // panic!("fail {}",&name.lexeme);
-            return Err(MainError::ResolvingError((name.line,name.lexeme,"Already a variable with this name".to_string())));
+            return Err(MainError::ResolvingError((name.span,name.lexeme,"Already a variable with this name".to_string())));
         }
-        (*scope).insert(name.lexeme, true);
+        let slot = scope.len();
+        scope.entry(name.lexeme).or_insert(Local{ defined:false, slot }).defined = true;
         Ok(())
     }
     //for resolving expressions i guess
-    fn resolve_local(&mut self,expr:Expr,name:Token){
-        for (i,ele) in self.scopes.clone().into_iter().rev().enumerate(){
-            if ele.contains_key(&name.lexeme){
-                self.interpreter.resolve(expr,i);
+    fn resolve_local(&mut self,id:usize,name:&Token){
+        for (i,ele) in self.scopes.iter().rev().enumerate(){
+            if let Some(local) = ele.get(&name.lexeme){
+                self.interpreter.resolve(id,i,local.slot);
                 break;
             }
         }
     }
-    fn resolve_function(&mut self,function:&mut Function,kind:FunctionType)->Result<(),MainError>{
+    fn resolve_function(&mut self,function:&Function,kind:FunctionType)->Result<(),MainError>{
         let enclosing_function = self.current_function;
+        let enclosing_loop_depth = self.loop_depth;
         self.current_function = kind;
+        self.loop_depth = 0;
         self.begin_scope();
+        let result = self.resolve_function_body(function);
+        self.end_scope();
+        self.current_function = enclosing_function;
+        self.loop_depth = enclosing_loop_depth;
+        // dbg!("From resolve function {}",self.scopes.clone());
+        result
+    }
+    fn resolve_function_body(&mut self,function:&Function)->Result<(),MainError>{
         for ele in function.params.clone(){
             self.delcare(ele.clone())?;
             self.define(ele.clone())?;
         }
-        self.resolve(function.body.clone())?;
-        self.end_scope();
-        self.current_function = enclosing_function;
-        // dbg!("From resolve function {}",self.scopes.clone());
+        self.resolve_statements(&mut function.body.clone());
         Ok(())
     }
 }
 impl<'a> VisitorExpr<Result<(),MainError>> for Resolver<'a>{
     fn visit_super_exp(&mut self,expr:&mut Super)->Result<(),MainError> {
         if self.current_class == ClassType::None{
-            return Err(MainError::ResolvingError((expr.keyword.line,expr.keyword.lexeme.clone(),"Can't use 'super' outside of a class.".to_string())));
+            return Err(MainError::ResolvingError((expr.keyword.span,expr.keyword.lexeme.clone(),"Can't use 'super' outside of a class.".to_string())));
         } else if self.current_class == ClassType::Class {
-            return Err(MainError::ResolvingError((expr.keyword.line,expr.keyword.lexeme.clone(),"Can't use 'super' in a class with no superclass.".to_string())));
+            return Err(MainError::ResolvingError((expr.keyword.span,expr.keyword.lexeme.clone(),"Can't use 'super' in a class with no superclass.".to_string())));
         }
-        self.resolve_local(Expr::Super(expr.clone()), expr.keyword.clone());
+        self.resolve_local(expr.id, &expr.keyword);
         Ok(())
     }
     fn visit_this_exp(&mut self,expr:&mut This)->Result<(),MainError> {
         if self.current_class == ClassType::None{
-            return Err(MainError::ResolvingError((expr.keyword.line,expr.keyword.lexeme.clone(),"Can't use 'this' outside of a class.".to_string())));
+            return Err(MainError::ResolvingError((expr.keyword.span,expr.keyword.lexeme.clone(),"Can't use 'this' outside of a class.".to_string())));
         }
-        self.resolve_local(Expr::This(expr.clone()), expr.keyword.clone());
+        self.resolve_local(expr.id, &expr.keyword);
         Ok(())
     }
     fn visit_variable_exp(&mut self,expr:&mut Variable)->Result<(),MainError> {
-        if !self.scopes.is_empty() && self.scopes.last_mut().unwrap().get(&expr.var.lexeme) == Some(&false) {
+        if !self.scopes.is_empty() && self.scopes.last_mut().unwrap().get(&expr.var.lexeme).map(|l| l.defined) == Some(false) {
             // dbg!(self.scopes.clone());
             // panic!("two");
-            return Err(MainError::ResolvingError((expr.var.line,expr.var.lexeme.clone(),"Can't read local variable in its own initializer.".to_string())));
+            return Err(MainError::ResolvingError((expr.var.span,expr.var.lexeme.clone(),"Can't read local variable in its own initializer.".to_string())));
         }
-        self.resolve_local(Expr::Variable(expr.clone()), expr.var.clone());
+        self.resolve_local(expr.id, &expr.var);
         Ok(())
     }
     fn visit_assign_exp(&mut self,expr:&mut Assign)->Result<(),MainError> {
-        self.resolve_expr((*expr.value).clone())?;
-        self.resolve_local(Expr::Assign((*expr).clone()), expr.name.clone());
+        self.resolve_expr(&mut expr.value)?;
+        self.resolve_local(expr.id, &expr.name);
         Ok(())
     }
     fn visit_binary_exp(&mut self,expr:&mut Binary)->Result<(),MainError> {
-        self.resolve_expr((*expr.left).clone())?;
-        self.resolve_expr((*expr.right).clone())?;
+        self.resolve_expr(&mut expr.left)?;
+        self.resolve_expr(&mut expr.right)?;
         Ok(())
     }
     fn visit_call_exp(&mut self,expr:&mut Call)->Result<(),MainError> {
         // println!("{:#?}",expr);
-        self.resolve_expr((*expr.callee).clone())?;
-        for ele in expr.arguments.clone(){
+        self.resolve_expr(&mut expr.callee)?;
+        for ele in expr.arguments.iter_mut(){
             self.resolve_expr(ele)?;
         }
         Ok(())
     }
     fn visit_grouping_exp(&mut self,expr:&mut Grouping)->Result<(),MainError> {
-        self.resolve_expr((*expr.expr).clone())?;
+        self.resolve_expr(&mut expr.expr)?;
         Ok(())
     }
     fn visit_literal_exp(&mut self,_:&mut Literal)->Result<(),MainError> {
         Ok(())
     }
     fn visit_logical_exp(&mut self,expr:&mut Logical)->Result<(),MainError> {
-        self.resolve_expr((*expr.left).clone())?;
-        self.resolve_expr((*expr.right).clone())?;
+        self.resolve_expr(&mut expr.left)?;
+        self.resolve_expr(&mut expr.right)?;
         Ok(())
     }
     fn visit_unary_exp(&mut self,expr:&mut Unary)->Result<(),MainError> {
-        self.resolve_expr((*expr.expr).clone())?;
+        self.resolve_expr(&mut expr.expr)?;
         Ok(())
     }
     fn visit_get_exp(&mut self,expr:&mut Get)->Result<(),MainError> {
         // println!("Resolve get {:#?}",expr);
-        self.resolve_expr((*expr.object).clone())?;
+        self.resolve_expr(&mut expr.object)?;
         Ok(())
     }
+    fn visit_lambda_exp(&mut self,expr:&mut Lambda)->Result<(),MainError> {
+        self.resolve_function(&expr.declaration, FunctionType::Function)
+    }
     fn visit_set_exp(&mut self,expr:&mut Set)->Result<(),MainError> {
-        self.resolve_expr((*expr.value).clone())?;
-        self.resolve_expr((*expr.object).clone())?;
+        self.resolve_expr(&mut expr.value)?;
+        self.resolve_expr(&mut expr.object)?;
+        Ok(())
+    }
+    fn visit_list_exp(&mut self,expr:&mut ListLiteral)->Result<(),MainError> {
+        for ele in expr.elements.iter_mut(){
+            self.resolve_expr(ele)?;
+        }
+        Ok(())
+    }
+    fn visit_map_exp(&mut self,expr:&mut MapLiteral)->Result<(),MainError> {
+        for (key, value) in expr.entries.iter_mut(){
+            self.resolve_expr(key)?;
+            self.resolve_expr(value)?;
+        }
+        Ok(())
+    }
+    fn visit_index_exp(&mut self,expr:&mut Index)->Result<(),MainError> {
+        self.resolve_expr(&mut expr.object)?;
+        self.resolve_expr(&mut expr.index)?;
+        Ok(())
+    }
+    fn visit_index_set_exp(&mut self,expr:&mut IndexSet)->Result<(),MainError> {
+        self.resolve_expr(&mut expr.object)?;
+        self.resolve_expr(&mut expr.index)?;
+        self.resolve_expr(&mut expr.value)?;
         Ok(())
     }
 }
 impl<'a> VisitorStmt<Result<(),MainError>> for Resolver<'a>{
+    fn visit_expression_stmt(&mut self,expr:&mut Expr)->Result<(),MainError>{
+        self.resolve_expr(expr)
+    }
     fn visit_block_stmt(&mut self,stmt:&mut Block)->Result<(),MainError>{
         self.begin_scope();
-        self.resolve(stmt.list.clone())?;
+        self.resolve_statements(&mut stmt.list);
         self.end_scope();
         Ok(())
     }
     fn visit_variable_stmt(&mut self,stmt:&mut Var)->Result<(),MainError> {
         self.delcare(stmt.name.clone())?;
-        if let Some(s) = stmt.initializer.clone(){
-            self.resolve_expr(s)?;
-        }
+        let initializer = match &mut stmt.initializer{
+            Some(s) => self.resolve_expr(s),
+            None => Ok(()),
+        };
+        //The variable is defined even if its initializer is broken, so later uses don't report it again
         self.define(stmt.name.clone())?;
-        Ok(())
+        initializer
 
     }
     fn visit_fn_stmt(&mut self,stmt:&mut Function)->Result<(),MainError> {
@@ -193,90 +261,132 @@ impl<'a> VisitorStmt<Result<(),MainError>> for Resolver<'a>{
         Ok(())
     }
     fn visit_if_stmt(&mut self,stmt:&mut If)->Result<(),MainError> {
-        self.resolve_expr(stmt.condition.clone())?;
-        self.resolve_stmt((*stmt.then_branch).clone())?;
-        if let Some(e) = stmt.else_branch.clone(){
-            self.resolve_stmt(*e)?;
+        self.resolve_expr(&mut stmt.condition)?;
+        self.resolve_stmt(&mut stmt.then_branch)?;
+        if let Some(e) = &mut stmt.else_branch{
+            self.resolve_stmt(e)?;
         }
         Ok(())
     }
     fn visit_print_stmt(&mut self,stmt:&mut Print)->Result<(),MainError> {
-        self.resolve_expr(stmt.expr.clone())?;
+        self.resolve_expr(&mut stmt.expr)?;
         Ok(())
     }
     fn visit_return_stmt(&mut self,stmt:&mut Return)->Result<(),MainError> {
         if self.current_function == FunctionType::None {
-            return Err(MainError::RuntimeError((stmt.keyword.line,stmt.keyword.lexeme.clone(),format!("Can't return from top-level code."))));
+            return Err(MainError::ResolvingError((stmt.keyword.span,stmt.keyword.lexeme.clone(),"Can't return from top-level code.".to_string())));
         }
         // println!("Return for {:?}",stmt);
-        if let  Expr::Literal_(l) = stmt.value.clone(){
-            if l.literal.type_ != TokenType::NIL{
-                if self.current_function == FunctionType::INITIALIZER{
-                    return Err(MainError::ResolvingError((stmt.keyword.line,stmt.keyword.lexeme.clone(),"Can't return a value from an initializer.".to_string())));
+        if let  Expr::Literal_(l) = &stmt.value{
+            if l.literal.type_ != TokenType::NIL
+                && self.current_function == FunctionType::Initializer{
+                    return Err(MainError::ResolvingError((stmt.keyword.span,stmt.keyword.lexeme.clone(),"Can't return a value from an initializer.".to_string())));
                 }
-            }
         }
-        self.resolve_expr(stmt.value.clone())?;
+        self.resolve_expr(&mut stmt.value)?;
         Ok(())
     }
     fn visit_while_stmt(&mut self,stmt:&mut While)->Result<(),MainError> {
-        self.resolve_expr(stmt.condition.clone())?;
-        self.resolve_stmt((*stmt.body).clone())?;
+        self.resolve_expr(&mut stmt.condition)?;
+        self.loop_depth += 1;
+        let body = self.resolve_stmt(&mut stmt.body);
+        self.loop_depth -= 1;
+        body?;
+        if let Some(inc) = &mut stmt.increment{
+            self.resolve_expr(inc)?;
+        }
+        Ok(())
+    }
+    fn visit_for_in_stmt(&mut self,stmt:&mut ForIn)->Result<(),MainError> {
+        self.resolve_expr(&mut stmt.iterable)?;
+        self.begin_scope();
+        self.delcare(stmt.name.clone())?;
+        self.define(stmt.name.clone())?;
+        self.loop_depth += 1;
+        let body = self.resolve_stmt(&mut stmt.body);
+        self.loop_depth -= 1;
+        self.end_scope();
+        body
+    }
+    fn visit_break_stmt(&mut self,stmt:&mut Break)->Result<(),MainError> {
+        if self.loop_depth == 0 {
+            return Err(MainError::ResolvingError((stmt.keyword.span,stmt.keyword.lexeme.clone(),"Can't use 'break' outside of a loop.".to_string())));
+        }
+        Ok(())
+    }
+    fn visit_continue_stmt(&mut self,stmt:&mut Continue)->Result<(),MainError> {
+        if self.loop_depth == 0 {
+            return Err(MainError::ResolvingError((stmt.keyword.span,stmt.keyword.lexeme.clone(),"Can't use 'continue' outside of a loop.".to_string())));
+        }
         Ok(())
     }
     fn visit_class_stmt(&mut self,stmt:&mut Class)->Result<(),MainError> {
         // let stmt = dbg!(stmt);
-        let enclosing_class = self.current_class;
-        self.current_class = ClassType::Class;
-
         self.delcare(stmt.name.clone())?;
         self.define(stmt.name.clone())?;
 
-        if let Some(_) = stmt.superclass {
-            if stmt.superclass.clone().unwrap().var.lexeme == stmt.name.lexeme{
-                return Err(MainError::ResolvingError((stmt.name.line,stmt.name.lexeme.clone(),"A class can't inherit from itself.".to_string())));
+        if stmt.superclass.is_some()
+            && stmt.superclass.clone().unwrap().var.lexeme == stmt.name.lexeme{
+                return Err(MainError::ResolvingError((stmt.name.span,stmt.name.lexeme.clone(),"A class can't inherit from itself.".to_string())));
             }
-        }
 
-        if let Some(s) = &stmt.superclass{
+        //From here on errors are recorded rather than returned, so scopes and the class type are always restored
+        let enclosing_class = self.current_class;
+        self.current_class = ClassType::Class;
+        if let Some(s) = &mut stmt.superclass{
             self.current_class = ClassType::SubClass;
             // println!("here");
-            self.resolve_expr(Expr::Variable(s.clone()))?;
+            if let Err(e) = s.accept(self){
+                self.errors.push(Diagnostic::from(e));
+            }
         }
-        if let Some(_) = stmt.superclass{
+        if stmt.superclass.is_some(){
             self.begin_scope();
             let x = self.scopes.last_mut().unwrap();
-            x.insert("super".to_string(), true);
+            x.insert("super".to_string(), Local{ defined:true, slot:0 });
         }
         self.begin_scope();
-        if let Some(v) = self.scopes.last_mut(){
-            v.insert("this".to_string(), true);
-        }else{
-            return Err(MainError::ResolvingError((stmt.name.line,stmt.name.lexeme.clone(),"Can't use this in a global scope".to_string())));
-        }
+        let x = self.scopes.last_mut().unwrap();
+        x.insert("this".to_string(), Local{ defined:true, slot:0 });
         for ele in &mut stmt.methods{
             let mut declaration = FunctionType::Method;
             if ele.name.lexeme == "init"{
-                declaration = FunctionType::INITIALIZER;
+                if ele.is_getter{
+                    self.errors.push(Diagnostic::from(MainError::ResolvingError((ele.name.span,ele.name.lexeme.clone(),"An initializer can't be a getter.".to_string()))));
+                }
+                declaration = FunctionType::Initializer;
+            }
+            if let Err(e) = self.resolve_function(ele, declaration){
+                self.errors.push(Diagnostic::from(e));
+            }
+        }
+        for ele in &stmt.static_methods{
+            if let Err(e) = self.resolve_function(ele, FunctionType::Method){
+                self.errors.push(Diagnostic::from(e));
             }
-            self.resolve_function(ele, declaration)?;
         }
 
         self.end_scope();
 
-        if let Some(_) = stmt.superclass{
+        if stmt.superclass.is_some(){
             self.end_scope()
         }
         self.current_class = enclosing_class;
         Ok(())
     }
 }
+//A variable declared in a local scope along with the slot it occupies in the runtime environment
+#[derive(Clone,Copy,Debug)]
+pub struct Local{
+    defined:bool,
+    slot:usize
+}
 #[derive(Clone,Copy,PartialEq)]
 enum FunctionType{
     None,
     Function,
     Method,
-    INITIALIZER
+    Initializer
 }
 #[derive(Clone,Copy,PartialEq)]
 enum ClassType{
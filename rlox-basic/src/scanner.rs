@@ -1,6 +1,7 @@
 use super::tokens::*;
 use TokenType::*;
 use super::MainError;
+use crate::diagnostics::Diagnostic;
 use Literals::*;
 use std::collections::HashMap;
 use std::string::String;
@@ -8,14 +9,22 @@ use crate::tokens;
 pub struct Scanner<'a> {
   source: &'a str,
   tokens: Vec<Token>,
+  //Byte offsets into the source
   start: usize,
   curr: usize,
   line: i32,
+  //Byte offset at which the current line begins, used to work out columns
+  line_start: usize,
+  //Line and column at which the current lexeme begins
+  start_line: i32,
+  start_column: usize,
   keywords:HashMap<String, TokenType>,
 }
 
 impl Scanner<'_>{
-  pub fn new(source: &str) -> Scanner {
+  //Scans `source` from the byte offset `start` on, which is the beginning of line `line`
+  //The REPL uses it to keep every input in one source, so older spans still point at the right text
+  pub fn resume(source: &str, start: usize, line: i32) -> Scanner<'_> {
     let mut keywords:HashMap<String, TokenType> = HashMap::new();
     keywords.insert("and".to_string(),    AND);
     keywords.insert("class".to_string(),  CLASS);
@@ -33,23 +42,37 @@ impl Scanner<'_>{
     keywords.insert("true".to_string(),   TRUE);
     keywords.insert("var".to_string(),    VAR);
     keywords.insert("while".to_string(),  WHILE);
+    keywords.insert("break".to_string(),  BREAK);
+    keywords.insert("continue".to_string(), CONTINUE);
+    keywords.insert("in".to_string(),     IN);
       Scanner {
-          source: source,
+          source,
           tokens: Vec::new(),
-          start: 0,
-          curr: 0,
-          line: 1,
+          start,
+          curr: start,
+          line,
+          line_start: start,
+          start_line: line,
+          start_column: 1,
           keywords,
       }
   }
 
-  pub fn scan_tokens(&mut self) -> Result<Vec<Token>,MainError> {
+  //Scanning goes on after an error, so every bad character in the source is reported at once
+  pub fn scan_tokens(&mut self) -> Result<Vec<Token>,Vec<Diagnostic>> {
+      let mut errors = Vec::new();
       while !self.is_at_end(){
           // We are at the beginning of the next lexeme.
-          self.start = self.curr;
-          self.scan_token()?;
+          self.begin_lexeme();
+          if let Err(e) = self.scan_token(){
+              errors.push(Diagnostic::from(e));
+          }
+      }
+      if !errors.is_empty(){
+          return Err(errors);
       }
-      let end = Token::new(EOF,"".to_string(),None,self.line); 
+      self.begin_lexeme();
+      let end = Token::new(EOF,"".to_string(),None,self.span());
       self.tokens.push(end);
       Ok(self.tokens.clone())
   }
@@ -61,6 +84,9 @@ impl Scanner<'_>{
           ')'=> self.add_token_a(RightParen)?,
           '{'=> self.add_token_a(LeftBrace)?,
           '}'=> self.add_token_a(RightBrace)?,
+          '['=> self.add_token_a(LeftBracket)?,
+          ']'=> self.add_token_a(RightBracket)?,
+          ':'=> self.add_token_a(COLON)?,
           ','=> self.add_token_a(COMMA)?,
           '.'=> self.add_token_a(DOT)?,
           '-'=> self.add_token_a(MINUS)?,
@@ -109,7 +135,7 @@ impl Scanner<'_>{
           '\r'=>(),
           '\t'=>(),
           //line change
-          '\n'=>{self.line += 1;},
+          '\n'=>self.new_line(),
           '"' => {
             self.getstring()?;
           },
@@ -120,20 +146,33 @@ impl Scanner<'_>{
             }else if Self::is_alpha(c){
               self.identifier()?;
             }else{
-              return Err(MainError::ScanningError((self.line,"".to_string(),"Unexpected character".to_string())));
+              return Err(MainError::ScanningError((self.span(),"".to_string(),"Unexpected character".to_string())));
             }
           },
       };
       Ok(())
   }
+  fn begin_lexeme(&mut self){
+    self.start = self.curr;
+    self.start_line = self.line;
+    self.start_column = self.source[self.line_start..self.start].chars().count() + 1;
+  }
+  fn new_line(&mut self){
+    self.line += 1;
+    self.line_start = self.curr;
+  }
+  //Span of the lexeme scanned so far
+  fn span(&self)->Span{
+    Span::new(self.start, self.curr, self.start_line, self.start_column)
+  }
   fn advance(&mut self)->Result<char, MainError>{
     let ans = self.char_at_curr()?;
-    self.curr+=1;
+    self.curr+=ans.len_utf8();
     Ok(ans)
   }
   fn add_token_b(&mut self,type_:TokenType,literal:Option<Literals>)->Result<(),MainError>{
     let lex = self.extract(self.start,self.curr)?;
-    self.tokens.push(Token::new(type_,lex.to_string(),literal,self.line));
+    self.tokens.push(Token::new(type_,lex.to_string(),literal,self.span()));
     Ok(())
   }
   fn add_token_a(&mut self,type_:TokenType)->Result<(),MainError>{
@@ -144,11 +183,11 @@ impl Scanner<'_>{
     self.curr >= self.source.len()
   }
   fn char_at_curr(&self)->Result<char,MainError>{
-    let Some(ans) = self.source.chars().nth(self.curr) else{
-      if self.source.len() == 0 {
-        return Err(MainError::ScanningError((self.line,format!("Trying to access character at {}(Indexing of 1)",self.curr+1),format!("Trying to access character in empty string"))));
+    let Some(ans) = self.source.get(self.curr..).and_then(|rest| rest.chars().next()) else{
+      if self.source.is_empty() {
+        return Err(MainError::ScanningError((self.span(),format!("Trying to access character at {}(Indexing of 1)",self.curr+1),"Trying to access character in empty string".to_string())));
       }else{
-        return Err(MainError::ScanningError((self.line,format!("Trying to access character at {}(Indexing of 1)",self.curr+1),format!("Index out of Bounds"))));
+        return Err(MainError::ScanningError((self.span(),format!("Trying to access character at {}(Indexing of 1)",self.curr+1),"Index out of Bounds".to_string())));
       }
     };
     Ok(ans)
@@ -156,7 +195,7 @@ impl Scanner<'_>{
   fn extract(&self,s:usize,e:usize)->Result<&str,MainError>{
     match self.source.get((s)..(e)){
       Some(ans) => Ok(ans),
-      None => Err(MainError::ScanningError((self.line,format!("Target range of characters {} to {}(Indexing of 1)",self.start+1,self.curr+1),format!("Unable to scan extract substring")))),
+      None => Err(MainError::ScanningError((self.span(),format!("Target range of characters {} to {}(Indexing of 1)",self.start+1,self.curr+1),"Unable to scan extract substring".to_string()))),
     }
   }
   fn match_(&mut self, expected:char)->Result<bool, MainError>{
@@ -166,7 +205,7 @@ impl Scanner<'_>{
     if  expected != self.char_at_curr()? {
       return Ok(false);
     }
-    self.curr+=1;
+    self.curr+=expected.len_utf8();
     Ok(true)
   }
   fn peek(&self)->Result<char,MainError>{
@@ -177,30 +216,22 @@ impl Scanner<'_>{
     }
   }
   fn peek_next(&self)->Result<char,MainError>{
-    if self.curr + 1 >= self.source.len() {
-      Ok('\0')
-    }else{
-      match self.source.chars().nth(self.curr + 1){
-        None => {
-          if self.source.len() == 0 {
-            Err(MainError::ScanningError((self.line,format!("Trying to access character at {}(Indexing of 1)",self.curr+1),format!("Trying to access character in empty string"))))
-          }else{
-            Err(MainError::ScanningError((self.line,format!("Trying to access character at {}(Indexing of 1)",self.curr+1),format!("Index out of Bounds"))))
-          }
-        },
-        Some(c) => Ok(c),
-      }
+    if self.is_at_end() {
+      return Ok('\0');
+    }
+    match self.source.get(self.curr..).and_then(|rest| rest.chars().nth(1)){
+      None => Ok('\0'),
+      Some(c) => Ok(c),
     }
   }
   fn getstring(&mut self)->Result<(), MainError>{
     while self.peek()? != '"' && !self.is_at_end(){
-      if self.peek()? == '\n'{
-        self.line+=1;
+      if self.advance()? == '\n'{
+        self.new_line();
       }
-      self.advance()?;
     }
     if self.is_at_end() {
-      return Err(MainError::ScanningError((self.line,"".to_string(),"Unterminated String".to_string())));
+      return Err(MainError::ScanningError((self.span(),"".to_string(),"Unterminated String".to_string())));
     }
     //Going past the closing ""
     self.advance()?;
@@ -209,7 +240,7 @@ impl Scanner<'_>{
     Ok(())
   }
   fn is_digit(c:char)->bool{
-    c >= '0' && c <= '9'
+    c.is_ascii_digit()
   }
   fn number(&mut self)->Result<(), MainError>{
     while Self::is_digit(self.peek()?) {
@@ -232,7 +263,7 @@ impl Scanner<'_>{
     self.add_token_b(NUMBER, Some(Number(tokens::MyFloat(ans))))
   }
   fn is_alpha(c:char)->bool{
-    (c >= 'a' && c<='z') || (c>='A' && c <='Z') || (c == '_')
+    c.is_ascii_lowercase() || c.is_ascii_uppercase() || (c == '_')
   }
   fn is_alpha_numeric(c:char)->bool{
     Self::is_alpha(c) || Self::is_digit(c)
@@ -248,7 +279,7 @@ impl Scanner<'_>{
       }else if self.keywords[text] == FALSE  {
         self.add_token_b(TRUE, Some(Literals::Boolean(false)))?;
       }else{
-        self.add_token_a(self.keywords[text].clone())?;
+        self.add_token_a(self.keywords[text])?;
       }
     }else{
       self.add_token_a(IDENTIFIER)?;
@@ -1,11 +1,10 @@
 use std::fmt;
-use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TokenType {
     // Single-character tokens.
-    LeftParen, RightParen, LeftBrace, RightBrace,
-    COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR,
+    LeftParen, RightParen, LeftBrace, RightBrace, LeftBracket, RightBracket,
+    COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR, COLON,
   
     // One or two character tokens.
     BANG, BangEqual,
@@ -19,6 +18,7 @@ pub enum TokenType {
     // Keywords.
     AND, CLASS, ELSE, FALSE, FUN, FOR, IF, NIL, OR,
     PRINT, RETURN, SUPER, THIS, TRUE, VAR, WHILE,
+    BREAK, CONTINUE, IN,
   
     EOF
 }
@@ -31,6 +31,8 @@ impl fmt::Display for TokenType {
             TokenType::RightParen => "RightParen",
             TokenType::LeftBrace => "LeftBrace",
             TokenType::RightBrace => "RightBrace",
+            TokenType::LeftBracket => "LeftBracket",
+            TokenType::RightBracket => "RightBracket",
             TokenType::COMMA => "COMMA",
             TokenType::DOT => "DOT",
             TokenType::MINUS => "MINUS",
@@ -38,6 +40,7 @@ impl fmt::Display for TokenType {
             TokenType::SEMICOLON => "SEMICOLON",
             TokenType::SLASH => "SLASH",
             TokenType::STAR => "STAR",
+            TokenType::COLON => "COLON",
   
             // One or two character tokens.
             TokenType::BANG => "BANG",
@@ -71,6 +74,9 @@ impl fmt::Display for TokenType {
             TokenType::TRUE => "TRUE",
             TokenType::VAR => "VAR",
             TokenType::WHILE => "WHILE",
+            TokenType::BREAK => "BREAK",
+            TokenType::CONTINUE => "CONTINUE",
+            TokenType::IN => "IN",
   
             // End of file.
             TokenType::EOF => "EOF",
@@ -78,25 +84,38 @@ impl fmt::Display for TokenType {
         write!(f, "{}", token_str)
     }
 }
+//Location of a piece of source code, `start..end` is a byte range into the source
+//`line` and `column` (counted in characters) are where the range begins, both start at 1
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span{
+    pub start:usize,
+    pub end:usize,
+    pub line:i32,
+    pub column:usize,
+}
+impl Span{
+    pub fn new(start:usize, end:usize, line:i32, column:usize)->Span{
+        Span { start, end, line, column }
+    }
+    //Smallest span covering both spans
+    pub fn to(self, other:Span)->Span{
+        let (first, last) = if other.start < self.start { (other, self) } else { (self, other) };
+        Span { start: first.start, end: first.end.max(last.end), line: first.line, column: first.column }
+    }
+}
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Token{
     pub type_:TokenType,
     pub lexeme:String,
     //we will later replace it with object
     pub literal:Option<Literals>,
-    pub line:i32,
+    pub span:Span,
 }
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct MyFloat(pub f64);
 
 impl Eq for MyFloat {}
 
-impl Ord for MyFloat {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
-    }
-}
-
 impl Hash for MyFloat {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.0.to_bits().hash(state);
@@ -111,8 +130,8 @@ pub enum Literals {
 }
 
 impl Token{
-    pub fn new(type_:TokenType, lexeme:String, literal:Option<Literals>, line:i32)->Token{
-        Token {type_,lexeme,literal,line}
+    pub fn new(type_:TokenType, lexeme:String, literal:Option<Literals>, span:Span)->Token{
+        Token {type_,lexeme,literal,span}
     }
     pub fn to_token_string(&self)->String{
         format!("{:?} {} {:?} ",self.type_,self.lexeme,self.literal)
@@ -0,0 +1,240 @@
+//!Runtime values of Lox, every expression evaluates to one of these
+use crate::callable::{LoxCallable, LoxClass, LoxFunction, LoxInstance, NativeFunction};
+use crate::gc;
+use crate::tokens::{Literals, MyFloat};
+use crate::vm::object::Object;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+#[derive(Clone, Debug)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Func(LoxFunction),
+    Native(NativeFunction),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    //Lists are shared, every copy of the value sees changes made through the others
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    //Functions, classes and instances of the bytecode VM
+    Vm(Object),
+}
+impl Value {
+    pub fn from_literal(literal: Option<Literals>) -> Value {
+        match literal {
+            None => Value::Nil,
+            Some(Literals::Boolean(b)) => Value::Boolean(b),
+            Some(Literals::Number(MyFloat(n))) => Value::Number(n),
+            Some(Literals::String(s)) => Value::String(s),
+        }
+    }
+    //Lox follows Ruby’s simple rule: false and nil are falsey
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Boolean(b) => *b,
+            _ => true,
+        }
+    }
+    pub fn new_list(elements: Vec<Value>) -> Value {
+        let list = Rc::new(RefCell::new(elements));
+        gc::track(&list);
+        Value::List(list)
+    }
+    pub fn new_map(map: LoxMap) -> Value {
+        let map = Rc::new(RefCell::new(map));
+        gc::track(&map);
+        Value::Map(map)
+    }
+    //Element at `index` of a list, map or string, errors are the message to report
+    pub fn get_index(&self, index: &Value) -> Result<Value, String> {
+        match self {
+            Value::List(l) => {
+                let l = l.borrow();
+                index.as_index(l.len()).map(|i| l[i].clone()).ok_or_else(|| index_error(index))
+            }
+            //Strings are indexed by character, not by byte
+            Value::String(s) => {
+                let chars: Vec<char> = s.chars().collect();
+                index.as_index(chars.len()).map(|i| Value::String(chars[i].to_string())).ok_or_else(|| index_error(index))
+            }
+            Value::Map(m) => m.borrow().get(&map_key(index)?).cloned().ok_or_else(|| format!("Key {} not found.", index)),
+            _ => Err("Only lists, maps and strings can be indexed.".to_string()),
+        }
+    }
+    pub fn set_index(&self, index: &Value, value: Value) -> Result<(), String> {
+        match self {
+            Value::List(l) => {
+                let mut l = l.borrow_mut();
+                let i = index.as_index(l.len()).ok_or_else(|| index_error(index))?;
+                l[i] = value;
+            }
+            //Assigning to a missing key adds it
+            Value::Map(m) => m.borrow_mut().insert(map_key(index)?, value),
+            _ => return Err("Only list and map elements can be assigned to.".to_string()),
+        }
+        Ok(())
+    }
+    //Position `index` refers to in a sequence of `len` elements, if it is a whole number within bounds
+    pub fn as_index(&self, len: usize) -> Option<usize> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && (*n as usize) < len => Some(*n as usize),
+            _ => None,
+        }
+    }
+}
+fn index_error(index: &Value) -> String {
+    match index {
+        Value::Number(n) if n.fract() == 0.0 => "Index out of range.".to_string(),
+        _ => "Index must be a whole number.".to_string(),
+    }
+}
+fn map_key(key: &Value) -> Result<MapKey, String> {
+    MapKey::from_value(key).ok_or_else(|| "Map keys must be strings or numbers.".to_string())
+}
+pub fn format_number(n: f64) -> String {
+    let n = n.to_string();
+    if let Some(stripped) = n.strip_suffix(".0") {
+        stripped.to_string()
+    } else {
+        n
+    }
+}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Func(func) => write!(f, "{}", func.give_string()),
+            Value::Native(func) => write!(f, "{}", func.give_string()),
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) => write!(f, "{}", instance.borrow().give_string()),
+            Value::List(list) => {
+                let elements: Vec<String> = list.borrow().iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::Map(map) => {
+                let entries: Vec<String> = map.borrow().iter().map(|(k, v)| format!("{}: {}", k.to_value(), v)).collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            Value::Vm(object) => write!(f, "{}", object),
+        }
+    }
+}
+//Equality never fails in Lox, values of different types are simply not equal
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Func(l), Value::Func(r)) => l.same_as(r),
+            (Value::Native(l), Value::Native(r)) => l.same_as(r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            (Value::List(l), Value::List(r)) => Rc::ptr_eq(l, r),
+            (Value::Map(l), Value::Map(r)) => Rc::ptr_eq(l, r),
+            (Value::Vm(l), Value::Vm(r)) => l.same_as(r),
+            _ => false,
+        }
+    }
+}
+//Values that can be used as map keys, numbers are compared bit by bit through MyFloat
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Number(MyFloat),
+    String(String),
+}
+impl MapKey {
+    //NaN never equals itself, so it can't be a key
+    pub fn from_value(value: &Value) -> Option<MapKey> {
+        match value {
+            Value::Number(n) if n.is_nan() => None,
+            //0 and -0 are equal but have different bits
+            Value::Number(n) => Some(MapKey::Number(MyFloat(if *n == 0.0 { 0.0 } else { *n }))),
+            Value::String(s) => Some(MapKey::String(s.clone())),
+            _ => None,
+        }
+    }
+    pub fn to_value(&self) -> Value {
+        match self {
+            MapKey::Number(MyFloat(n)) => Value::Number(*n),
+            MapKey::String(s) => Value::String(s.clone()),
+        }
+    }
+}
+//Map that remembers the order its keys were first inserted in
+#[derive(Clone, Debug, Default)]
+pub struct LoxMap {
+    entries: Vec<(MapKey, Value)>,
+    //Position of every key in `entries`
+    slots: HashMap<MapKey, usize>,
+}
+impl LoxMap {
+    pub fn new() -> LoxMap {
+        LoxMap::default()
+    }
+    pub fn get(&self, key: &MapKey) -> Option<&Value> {
+        self.slots.get(key).map(|&slot| &self.entries[slot].1)
+    }
+    //Overwriting a key keeps its position
+    pub fn insert(&mut self, key: MapKey, value: Value) {
+        match self.slots.get(&key) {
+            Some(&slot) => self.entries[slot].1 = value,
+            None => {
+                self.slots.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+    pub fn remove(&mut self, key: &MapKey) -> Option<Value> {
+        let slot = self.slots.remove(key)?;
+        let (_, value) = self.entries.remove(slot);
+        for (key, _) in &self.entries[slot..] {
+            if let Some(s) = self.slots.get_mut(key) {
+                *s -= 1;
+            }
+        }
+        Some(value)
+    }
+    pub fn contains_key(&self, key: &MapKey) -> bool {
+        self.slots.contains_key(key)
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &(MapKey, Value)> {
+        self.entries.iter()
+    }
+}
+//Position of a running for-in loop
+#[derive(Debug)]
+pub enum Cursor {
+    //Lists are read as the loop goes, so elements pushed inside it are visited as well
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    //Map keys and string characters are taken when the loop starts
+    Values(std::vec::IntoIter<Value>),
+    //Object handed back by `iterator()`, driven through its `hasNext()` and `next()` methods
+    Iterator(Value),
+}
+impl Cursor {
+    //Cursor over a built-in collection, other values are iterated through the iterator protocol if at all
+    pub fn over(iterable: &Value) -> Option<Cursor> {
+        match iterable {
+            Value::List(l) => Some(Cursor::List(l.clone(), 0)),
+            Value::Map(m) => Some(Cursor::Values(m.borrow().iter().map(|(k, _)| k.to_value()).collect::<Vec<_>>().into_iter())),
+            Value::String(s) => Some(Cursor::Values(s.chars().map(|c| Value::String(c.to_string())).collect::<Vec<_>>().into_iter())),
+            _ => None,
+        }
+    }
+}
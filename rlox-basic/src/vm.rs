@@ -0,0 +1,569 @@
+//!Stack based virtual machine running the bytecode made by the compiler
+//!
+//!Globals, natives and the output are shared with the tree-walk interpretor, so both engines see
+//!the same session
+pub mod chunk;
+pub mod compiler;
+pub mod disassembler;
+pub mod module;
+pub mod object;
+
+use crate::callable::{LoxCallable, NativeFunction};
+use crate::diagnostics::fold_frames;
+use crate::gc;
+use crate::interpretor::Interpretor;
+use crate::tokens::Span;
+use crate::value::{format_number, Cursor, LoxMap, MapKey, Value};
+use crate::MainError;
+use chunk::OpCode;
+use object::{BoundMethod, Class, Closure, Function, Instance, Object, Upvalue};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+//Deepest the call stack may get before the script is stopped
+const FRAMES_MAX: usize = 4096;
+
+//A running call, its locals start at `base` on the stack where slot 0 holds the callee or receiver
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    //Start of the instruction being executed, errors are reported at its span
+    op: usize,
+    base: usize,
+}
+
+pub struct Vm<'a> {
+    interpretor: &'a mut Interpretor,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    //Upvalues still pointing at the stack, sorted by slot
+    open_upvalues: Vec<(usize, Rc<RefCell<Upvalue>>)>,
+}
+impl<'a> Vm<'a> {
+    pub fn new(interpretor: &'a mut Interpretor) -> Vm<'a> {
+        Vm { interpretor, stack: Vec::new(), frames: Vec::new(), open_upvalues: Vec::new() }
+    }
+    //Runs a compiled script and hands back what it returned
+    pub fn interpret(&mut self, function: Rc<Function>) -> Result<Value, MainError> {
+        let closure = Rc::new(Closure { function, upvalues: Vec::new() });
+        gc::track(&closure);
+        self.stack.push(Value::Vm(Object::Closure(closure.clone())));
+        if let Err(error) = self.call_closure(closure, 0).and_then(|_| self.run(0)) {
+            self.close_upvalues(0);
+            return Err(error);
+        }
+        Ok(self.pop())
+    }
+
+    //Executes until the call stack is back to `depth` frames
+    fn run(&mut self, depth: usize) -> Result<(), MainError> {
+        while self.frames.len() > depth {
+            if let Err(error) = self.step() {
+                //The stack goes away with the error, closures that escaped must not point into it anymore
+                self.close_upvalues(0);
+                return Err(self.trace(error, None));
+            }
+        }
+        Ok(())
+    }
+    fn step(&mut self) -> Result<(), MainError> {
+        let frame = self.frames.last_mut().unwrap();
+        frame.op = frame.ip;
+        let byte = self.read_byte();
+        let Some(op) = OpCode::from_byte(byte) else {
+            return Err(runtime_error("", &format!("Unknown opcode {}.", byte)));
+        };
+        match op {
+            OpCode::Constant => {
+                let constant = self.read_constant();
+                self.stack.push(constant);
+            }
+            OpCode::Nil => self.stack.push(Value::Nil),
+            OpCode::True => self.stack.push(Value::Boolean(true)),
+            OpCode::False => self.stack.push(Value::Boolean(false)),
+            OpCode::Pop => {
+                self.pop();
+            }
+            OpCode::GetLocal => {
+                let slot = self.base() + self.read_byte() as usize;
+                self.stack.push(self.stack[slot].clone());
+            }
+            OpCode::SetLocal => {
+                let slot = self.base() + self.read_byte() as usize;
+                self.stack[slot] = self.peek(0).clone();
+            }
+            OpCode::GetGlobal => {
+                let name = self.read_name();
+                let value = self.interpretor.globals.borrow().map.get(&name).cloned();
+                match value {
+                    Some(value) => self.stack.push(value),
+                    None => return Err(runtime_error(&name, "Undeclared variable or Function")),
+                }
+            }
+            OpCode::DefineGlobal => {
+                let name = self.read_name();
+                let value = self.pop();
+                self.interpretor.globals.borrow_mut().define_(&name, value);
+            }
+            OpCode::SetGlobal => {
+                let name = self.read_name();
+                let value = self.peek(0).clone();
+                let mut globals = self.interpretor.globals.borrow_mut();
+                match globals.map.get_mut(&name) {
+                    Some(global) => *global = value,
+                    None => return Err(runtime_error(&name, "Undefined Variable")),
+                }
+            }
+            OpCode::GetUpvalue => {
+                let index = self.read_byte() as usize;
+                let upvalue = self.frames.last().unwrap().closure.upvalues[index].clone();
+                let value = match &*upvalue.borrow() {
+                    Upvalue::Open(slot) => self.stack[*slot].clone(),
+                    Upvalue::Closed(value) => value.clone(),
+                };
+                self.stack.push(value);
+            }
+            OpCode::SetUpvalue => {
+                let index = self.read_byte() as usize;
+                let upvalue = self.frames.last().unwrap().closure.upvalues[index].clone();
+                let value = self.peek(0).clone();
+                let mut upvalue = upvalue.borrow_mut();
+                match &mut *upvalue {
+                    Upvalue::Open(slot) => self.stack[*slot] = value,
+                    Upvalue::Closed(closed) => *closed = value,
+                }
+            }
+            OpCode::GetProperty => {
+                let name = self.read_name();
+                self.get_property(&name)?;
+            }
+            OpCode::SetProperty => {
+                let name = self.read_name();
+                let value = self.pop();
+                let Value::Vm(Object::Instance(instance)) = self.pop() else {
+                    return Err(runtime_error(&name, "Only instances have fields."));
+                };
+                instance.borrow_mut().fields.insert(name, value.clone());
+                self.stack.push(value);
+            }
+            OpCode::GetSuper => {
+                let name = self.read_name();
+                let Value::Vm(Object::Class(superclass)) = self.pop() else {
+                    return Err(runtime_error("super", "Expected a class associated with super"));
+                };
+                let receiver = self.pop();
+                //Inside a static method `this` is the class, so the lookup happens in the static method table
+                let method = match &receiver {
+                    Value::Vm(Object::Instance(_)) => superclass.find_method(&name),
+                    Value::Vm(Object::Class(_)) => superclass.find_static_method(&name),
+                    _ => return Err(runtime_error("super", "Expected an instance associated with this")),
+                };
+                let Some(method) = method else {
+                    return Err(runtime_error("super", "Undefined Property"));
+                };
+                self.bind_method(receiver, method)?;
+            }
+            OpCode::GetIndex => {
+                let index = self.pop();
+                let object = self.pop();
+                let value = object.get_index(&index).map_err(|message| runtime_error("]", &message))?;
+                self.stack.push(value);
+            }
+            OpCode::SetIndex => {
+                let value = self.pop();
+                let index = self.pop();
+                let object = self.pop();
+                object.set_index(&index, value.clone()).map_err(|message| runtime_error("]", &message))?;
+                self.stack.push(value);
+            }
+            OpCode::Equal => {
+                let (left, right) = self.pop_pair();
+                self.stack.push(Value::Boolean(left == right));
+            }
+            OpCode::NotEqual => {
+                let (left, right) = self.pop_pair();
+                self.stack.push(Value::Boolean(left != right));
+            }
+            OpCode::Greater => self.compare(">", |l, r| l > r)?,
+            OpCode::GreaterEqual => self.compare(">=", |l, r| l >= r)?,
+            OpCode::Less => self.compare("<", |l, r| l < r)?,
+            OpCode::LessEqual => self.compare("<=", |l, r| l <= r)?,
+            OpCode::Add => {
+                let value = match self.pop_pair() {
+                    (Value::Number(l), Value::Number(r)) => Value::Number(l + r),
+                    (Value::String(l), Value::String(r)) => Value::String(l + &r),
+                    (Value::String(l), Value::Number(r)) => Value::String(l + &format_number(r)),
+                    (Value::Number(l), Value::String(r)) => Value::String(format_number(l) + &r),
+                    _ => return Err(runtime_error("+", "Both operands are not numbers or strings")),
+                };
+                self.stack.push(value);
+            }
+            OpCode::Subtract => self.arithmetic("-", |l, r| l - r)?,
+            OpCode::Multiply => self.arithmetic("*", |l, r| l * r)?,
+            OpCode::Divide => self.arithmetic("/", |l, r| l / r)?,
+            OpCode::Not => {
+                let value = self.pop();
+                self.stack.push(Value::Boolean(!value.is_truthy()));
+            }
+            OpCode::Negate => match self.pop() {
+                Value::Number(n) => self.stack.push(Value::Number(-n)),
+                _ => return Err(runtime_error("-", "Operand must be a number")),
+            },
+            OpCode::Print => {
+                let value = self.pop();
+                self.interpretor.write_line(&value.to_string())?;
+            }
+            OpCode::Jump => {
+                let offset = self.read_u16() as usize;
+                self.frames.last_mut().unwrap().ip += offset;
+            }
+            OpCode::JumpIfFalse => {
+                let offset = self.read_u16() as usize;
+                if !self.peek(0).is_truthy() {
+                    self.frames.last_mut().unwrap().ip += offset;
+                }
+            }
+            OpCode::Loop => {
+                let offset = self.read_u16() as usize;
+                self.frames.last_mut().unwrap().ip -= offset;
+                gc::collect_if_needed();
+            }
+            OpCode::Call => {
+                //Every program allocating without bound has to loop or call, so collecting here is often enough
+                gc::collect_if_needed();
+                let argc = self.read_byte() as usize;
+                let callee = self.peek(argc).clone();
+                self.call_value(callee, argc)?;
+            }
+            OpCode::Closure => {
+                let Value::Vm(Object::Function(function)) = self.read_constant() else {
+                    return Err(runtime_error("", "Closure over a constant that is not a function."));
+                };
+                let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                for _ in 0..function.upvalue_count {
+                    let is_local = self.read_byte() == 1;
+                    let index = self.read_byte() as usize;
+                    if is_local {
+                        upvalues.push(self.capture_upvalue(self.base() + index));
+                    } else {
+                        upvalues.push(self.frames.last().unwrap().closure.upvalues[index].clone());
+                    }
+                }
+                let closure = Rc::new(Closure { function, upvalues });
+                gc::track(&closure);
+                self.stack.push(Value::Vm(Object::Closure(closure)));
+            }
+            OpCode::CloseUpvalue => {
+                self.close_upvalues(self.stack.len() - 1);
+                self.pop();
+            }
+            OpCode::Return => {
+                let result = self.pop();
+                let frame = self.frames.pop().unwrap();
+                self.close_upvalues(frame.base);
+                self.stack.truncate(frame.base);
+                self.stack.push(result);
+            }
+            OpCode::Class => {
+                let name = self.read_name();
+                let class = Rc::new(Class::new(name));
+                gc::track(&class);
+                self.stack.push(Value::Vm(Object::Class(class)));
+            }
+            OpCode::Inherit => {
+                let Value::Vm(Object::Class(superclass)) = self.peek(1).clone() else {
+                    return Err(runtime_error("", "Superclass must be a class."));
+                };
+                let Value::Vm(Object::Class(class)) = self.pop() else {
+                    unreachable!("the compiler puts the subclass on top of the superclass");
+                };
+                class.methods.borrow_mut().extend(superclass.methods.borrow().clone());
+                class.static_methods.borrow_mut().extend(superclass.static_methods.borrow().clone());
+            }
+            OpCode::Method | OpCode::StaticMethod => {
+                let name = self.read_name();
+                let Value::Vm(Object::Closure(method)) = self.pop() else {
+                    unreachable!("methods are always compiled to closures");
+                };
+                let Value::Vm(Object::Class(class)) = self.peek(0) else {
+                    unreachable!("the class stays on the stack while its methods are added");
+                };
+                let table = if op == OpCode::Method { &class.methods } else { &class.static_methods };
+                table.borrow_mut().insert(name, method);
+            }
+            OpCode::BuildList => {
+                let count = self.read_u16() as usize;
+                let elements = self.stack.split_off(self.stack.len() - count);
+                self.stack.push(Value::new_list(elements));
+            }
+            OpCode::NewMap => self.stack.push(Value::new_map(LoxMap::new())),
+            OpCode::MapInsert => {
+                let value = self.pop();
+                let key = self.pop();
+                let Some(key) = MapKey::from_value(&key) else {
+                    return Err(runtime_error(&key.to_string(), "Map keys must be strings or numbers."));
+                };
+                if let Value::Map(map) = self.peek(0) {
+                    map.borrow_mut().insert(key, value);
+                }
+            }
+            OpCode::Iterate => {
+                let iterable = self.pop();
+                let cursor = match Cursor::over(&iterable) {
+                    Some(cursor) => cursor,
+                    None => match iterable {
+                        Value::Vm(Object::Instance(_)) => Cursor::Iterator(self.call_protocol_method(&iterable, "iterator")?),
+                        _ => {
+                            return Err(runtime_error(
+                                &iterable.to_string(),
+                                "Only lists, maps, strings and instances with an iterator() method can be iterated.",
+                            ))
+                        }
+                    },
+                };
+                let cursor = Rc::new(RefCell::new(cursor));
+                gc::track(&cursor);
+                self.stack.push(Value::Vm(Object::Cursor(cursor)));
+            }
+            OpCode::ForNext => {
+                let offset = self.read_u16() as usize;
+                let Value::Vm(Object::Cursor(cursor)) = self.peek(0).clone() else {
+                    unreachable!("the cursor is on top of the stack when an iteration starts");
+                };
+                match self.next_element(&cursor)? {
+                    Some(element) => self.stack.push(element),
+                    None => self.frames.last_mut().unwrap().ip += offset,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frames.last_mut().unwrap();
+        let byte = frame.closure.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frames.last_mut().unwrap();
+        let value = frame.closure.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_u16() as usize;
+        self.frames.last().unwrap().closure.function.chunk.constants[index].clone()
+    }
+    fn read_name(&mut self) -> String {
+        match self.read_constant() {
+            Value::String(name) => name,
+            other => other.to_string(),
+        }
+    }
+    fn base(&self) -> usize {
+        self.frames.last().unwrap().base
+    }
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler keeps the stack balanced")
+    }
+    fn pop_pair(&mut self) -> (Value, Value) {
+        let right = self.pop();
+        (self.pop(), right)
+    }
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+    fn arithmetic(&mut self, operator: &str, apply: fn(f64, f64) -> f64) -> Result<(), MainError> {
+        match self.pop_pair() {
+            (Value::Number(l), Value::Number(r)) => self.stack.push(Value::Number(apply(l, r))),
+            _ => return Err(runtime_error(operator, "Both operands are not numbers")),
+        }
+        Ok(())
+    }
+    fn compare(&mut self, operator: &str, apply: fn(f64, f64) -> bool) -> Result<(), MainError> {
+        match self.pop_pair() {
+            (Value::Number(l), Value::Number(r)) => self.stack.push(Value::Boolean(apply(l, r))),
+            _ => return Err(runtime_error(operator, "Both operands are not numbers")),
+        }
+        Ok(())
+    }
+
+    //Replaces the object on top of the stack with the property `name` of it
+    fn get_property(&mut self, name: &str) -> Result<(), MainError> {
+        let object = self.pop();
+        match &object {
+            Value::Vm(Object::Instance(instance)) => {
+                let field = instance.borrow().fields.get(name).cloned();
+                if let Some(field) = field {
+                    self.stack.push(field);
+                    return Ok(());
+                }
+                let method = instance.borrow().class.find_method(name);
+                match method {
+                    Some(method) => self.bind_method(object, method),
+                    None => Err(runtime_error(name, "Undefined property/mehtod.")),
+                }
+            }
+            //Static methods are bound to the class, so `this` inside them refers to the class object
+            Value::Vm(Object::Class(class)) => match class.find_static_method(name) {
+                Some(method) => self.bind_method(object, method),
+                None => Err(runtime_error(name, "Undefined static method.")),
+            },
+            _ => Err(runtime_error(name, "Only instances have properties")),
+        }
+    }
+    //Pushes the method bound to `receiver`, getters are called right away and push their result instead
+    fn bind_method(&mut self, receiver: Value, method: Rc<Closure>) -> Result<(), MainError> {
+        if method.function.is_getter {
+            self.stack.push(receiver);
+            return self.call_closure(method, 0);
+        }
+        let bound = Rc::new(BoundMethod { receiver, method });
+        gc::track(&bound);
+        self.stack.push(Value::Vm(Object::BoundMethod(bound)));
+        Ok(())
+    }
+
+    //Starts a call of the callee sitting below its `argc` arguments, natives are done when this returns
+    fn call_value(&mut self, callee: Value, argc: usize) -> Result<(), MainError> {
+        match callee {
+            Value::Vm(Object::Closure(closure)) => self.call_closure(closure, argc),
+            Value::Vm(Object::BoundMethod(bound)) => {
+                let slot = self.stack.len() - argc - 1;
+                self.stack[slot] = bound.receiver.clone();
+                self.call_closure(bound.method.clone(), argc)
+            }
+            Value::Vm(Object::Class(class)) => {
+                let slot = self.stack.len() - argc - 1;
+                let instance = Instance { class: class.clone(), fields: HashMap::new() };
+                let instance = Rc::new(RefCell::new(instance));
+                gc::track(&instance);
+                self.stack[slot] = Value::Vm(Object::Instance(instance));
+                match class.find_method("init") {
+                    Some(initializer) => self.call_closure(initializer, argc),
+                    None if argc != 0 => Err(arity_error(0, argc)),
+                    None => Ok(()),
+                }
+            }
+            Value::Native(native) => self.call_native(native, argc),
+            _ => Err(runtime_error("(", "Can only call functions and classes")),
+        }
+    }
+    fn call_closure(&mut self, closure: Rc<Closure>, argc: usize) -> Result<(), MainError> {
+        if argc != closure.function.arity as usize {
+            return Err(arity_error(closure.function.arity, argc));
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err(runtime_error("", "Stack overflow."));
+        }
+        let base = self.stack.len() - argc - 1;
+        self.frames.push(Frame { closure, ip: 0, op: 0, base });
+        Ok(())
+    }
+    fn call_native(&mut self, mut native: NativeFunction, argc: usize) -> Result<(), MainError> {
+        if argc != native.arity() as usize {
+            return Err(arity_error(native.arity(), argc));
+        }
+        let arguments = self.stack.split_off(self.stack.len() - argc);
+        self.pop();
+        let result = native.call(self.interpretor, arguments).map_err(|e| self.trace(e, Some(&native.name)))?;
+        self.stack.push(result);
+        Ok(())
+    }
+    //Calls a value from Rust code and waits for the result
+    fn call_sync(&mut self, callee: Value, arguments: Vec<Value>) -> Result<Value, MainError> {
+        let depth = self.frames.len();
+        let argc = arguments.len();
+        self.stack.push(callee.clone());
+        self.stack.extend(arguments);
+        self.call_value(callee, argc)?;
+        if self.frames.len() > depth {
+            self.run(depth)?;
+        }
+        Ok(self.pop())
+    }
+    //Methods of the iteration protocol are looked up on the class, fields of the same name don't count
+    fn call_protocol_method(&mut self, object: &Value, name: &str) -> Result<Value, MainError> {
+        let method = match object {
+            Value::Vm(Object::Instance(instance)) => instance.borrow().class.find_method(name),
+            _ => None,
+        };
+        let Some(method) = method else {
+            return Err(runtime_error("in", &format!("{} has no '{}' method to iterate with.", object, name)));
+        };
+        let bound = BoundMethod { receiver: object.clone(), method };
+        self.call_sync(Value::Vm(Object::BoundMethod(Rc::new(bound))), Vec::new())
+    }
+    fn next_element(&mut self, cursor: &Rc<RefCell<Cursor>>) -> Result<Option<Value>, MainError> {
+        //The protocol methods run Lox code, which must not find the cursor borrowed
+        let object = match &mut *cursor.borrow_mut() {
+            Cursor::List(list, i) => {
+                let element = list.borrow().get(*i).cloned();
+                *i += 1;
+                return Ok(element);
+            }
+            Cursor::Values(values) => return Ok(values.next()),
+            Cursor::Iterator(object) => object.clone(),
+        };
+        if !self.call_protocol_method(&object, "hasNext")?.is_truthy() {
+            return Ok(None);
+        }
+        self.call_protocol_method(&object, "next").map(Some)
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let at = self.open_upvalues.partition_point(|(open, _)| *open < slot);
+        if let Some((open, upvalue)) = self.open_upvalues.get(at) {
+            if *open == slot {
+                return upvalue.clone();
+            }
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        gc::track(&upvalue);
+        self.open_upvalues.insert(at, (slot, upvalue.clone()));
+        upvalue
+    }
+    //Moves every variable at `from` or above off the stack into the upvalues capturing it
+    fn close_upvalues(&mut self, from: usize) {
+        let at = self.open_upvalues.partition_point(|(open, _)| *open < from);
+        for (slot, upvalue) in self.open_upvalues.drain(at..) {
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack[slot].clone());
+        }
+    }
+
+    //Points a runtime error at the instruction that raised it and attaches the call stack when inside a call
+    fn trace(&self, error: MainError, native: Option<&str>) -> MainError {
+        let MainError::RuntimeError((span, place, message)) = error else {
+            return error;
+        };
+        let op_span = |frame: &Frame| frame.closure.function.chunk.spans[frame.op];
+        let call_site = self.frames.last().map_or(Span::default(), op_span);
+        //Native functions don't know where they were called from, so their errors point at the call
+        let span = if span.line > 0 { span } else { call_site };
+        if self.frames.len() <= 1 && native.is_none() {
+            return MainError::RuntimeError((span, place, message));
+        }
+        let mut trace = Vec::new();
+        if let Some(native) = native {
+            trace.push(format!("at {} (line {})", native, span.line));
+        }
+        //Every caller is shown at the call it is waiting on
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            let function = &frame.closure.function;
+            let name = if function.name.is_empty() { "<script>" } else { &function.name };
+            let line = if i == 0 && native.is_none() { span.line } else { op_span(frame).line };
+            trace.push(format!("at {} (line {})", name, line));
+        }
+        MainError::Traced(Box::new(MainError::RuntimeError((span, place, message))), fold_frames(trace))
+    }
+}
+
+//Errors don't know where they happened until `trace` points them at the current instruction
+fn runtime_error(place: &str, message: &str) -> MainError {
+    MainError::RuntimeError((Span::default(), place.to_string(), message.to_string()))
+}
+fn arity_error(arity: u32, argc: usize) -> MainError {
+    runtime_error("(", &format!("Expected {} argumetns but got {} .", arity, argc))
+}
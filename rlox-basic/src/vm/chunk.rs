@@ -0,0 +1,106 @@
+//!Compiled code of a single function along with its constants and source locations
+use crate::tokens::Span;
+use crate::value::Value;
+
+//Operands follow the opcode in the code stream, constants and jumps take two bytes, big endian
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    //constant
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    //slot
+    GetLocal,
+    SetLocal,
+    //name constant
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    //upvalue index
+    GetUpvalue,
+    SetUpvalue,
+    //name constant
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    GetIndex,
+    SetIndex,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    //offset forward
+    Jump,
+    JumpIfFalse,
+    //offset backward
+    Loop,
+    //argument count
+    Call,
+    //function constant, then a local flag and an index for every upvalue
+    Closure,
+    CloseUpvalue,
+    Return,
+    //name constant
+    Class,
+    Inherit,
+    Method,
+    StaticMethod,
+    //element count
+    BuildList,
+    NewMap,
+    MapInsert,
+    Iterate,
+    //offset forward, taken once the loop is done
+    ForNext,
+}
+impl OpCode {
+    //Every opcode in the order of its byte
+    const ALL: [OpCode; 46] = [
+        OpCode::Constant, OpCode::Nil, OpCode::True, OpCode::False, OpCode::Pop,
+        OpCode::GetLocal, OpCode::SetLocal, OpCode::GetGlobal, OpCode::DefineGlobal, OpCode::SetGlobal,
+        OpCode::GetUpvalue, OpCode::SetUpvalue, OpCode::GetProperty, OpCode::SetProperty, OpCode::GetSuper,
+        OpCode::GetIndex, OpCode::SetIndex, OpCode::Equal, OpCode::NotEqual, OpCode::Greater,
+        OpCode::GreaterEqual, OpCode::Less, OpCode::LessEqual, OpCode::Add, OpCode::Subtract,
+        OpCode::Multiply, OpCode::Divide, OpCode::Not, OpCode::Negate, OpCode::Print,
+        OpCode::Jump, OpCode::JumpIfFalse, OpCode::Loop, OpCode::Call, OpCode::Closure,
+        OpCode::CloseUpvalue, OpCode::Return, OpCode::Class, OpCode::Inherit, OpCode::Method,
+        OpCode::StaticMethod, OpCode::BuildList, OpCode::NewMap, OpCode::MapInsert, OpCode::Iterate,
+        OpCode::ForNext,
+    ];
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        OpCode::ALL.get(byte as usize).copied()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    //Line table, the source location every byte of `code` was compiled from
+    pub spans: Vec<Span>,
+}
+impl Chunk {
+    pub fn write(&mut self, byte: u8, span: Span) {
+        self.code.push(byte);
+        self.spans.push(span);
+    }
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
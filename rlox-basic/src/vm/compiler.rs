@@ -0,0 +1,603 @@
+//!Compiles the resolved syntax tree into bytecode, one chunk per function
+//!
+//!The resolver has already rejected invalid programs, the compiler only works out where every
+//!variable lives at runtime: a slot on the stack, an upvalue or a global looked up by name
+use super::chunk::{Chunk, OpCode};
+use super::object::{self, Object};
+use crate::parser::expr::*;
+use crate::parser::stmts::*;
+use crate::tokens::{Span, Token, TokenType};
+use crate::value::{MapKey, Value};
+use crate::MainError;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+//Name of the hidden local holding the cursor of a for-in loop, no identifier can clash with it
+const CURSOR: &str = " cursor";
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    //Instance and static methods, `this` lives in slot 0
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+    //Captured locals are moved off the stack when their scope ends
+    captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueRef {
+    index: u8,
+    //Whether the variable is a local of the enclosing function or one of its upvalues
+    is_local: bool,
+}
+
+struct Loop {
+    //Scope depth outside of the body, `break` and `continue` discard every local deeper than this
+    depth: usize,
+    //Where `continue` jumps to, unknown for while loops until the increment is compiled
+    start: Option<usize>,
+    continues: Vec<usize>,
+    breaks: Vec<usize>,
+}
+
+struct FunctionState {
+    function: object::Function,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    depth: usize,
+    loops: Vec<Loop>,
+    //Numbers and strings already in the constant pool
+    constants: HashMap<MapKey, u16>,
+}
+impl FunctionState {
+    fn new(name: String, kind: FunctionKind) -> FunctionState {
+        //Slot 0 holds the function being called, or the receiver in methods
+        let receiver = if kind == FunctionKind::Method || kind == FunctionKind::Initializer { "this" } else { "" };
+        FunctionState {
+            function: object::Function { name, ..object::Function::default() },
+            kind,
+            locals: vec![Local { name: receiver.to_string(), depth: 0, captured: false }],
+            upvalues: Vec::new(),
+            depth: 0,
+            loops: Vec::new(),
+            constants: HashMap::new(),
+        }
+    }
+}
+
+pub struct Compiler {
+    //Functions being compiled, the innermost one is last
+    functions: Vec<FunctionState>,
+}
+impl Compiler {
+    //The script hands back the value of its last statement when that is an expression
+    pub fn compile(statements: &mut [Stmt]) -> Result<Rc<object::Function>, MainError> {
+        let mut compiler = Compiler { functions: vec![FunctionState::new(String::new(), FunctionKind::Script)] };
+        let count = statements.len();
+        let mut end = Span::default();
+        for (i, statement) in statements.iter_mut().enumerate() {
+            end = statement.span();
+            match statement {
+                Stmt::Expression(expr) if i + 1 == count => {
+                    compiler.expression(expr)?;
+                    compiler.emit_op(OpCode::Return, end);
+                    return Ok(Rc::new(compiler.functions.remove(0).function));
+                }
+                _ => statement.accept(&mut compiler)?,
+            }
+        }
+        compiler.emit_return(end);
+        Ok(Rc::new(compiler.functions.remove(0).function))
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().function.chunk
+    }
+    fn expression(&mut self, expr: &mut Expr) -> Result<(), MainError> {
+        expr.accept(self)
+    }
+    fn statement(&mut self, stmt: &mut Stmt) -> Result<(), MainError> {
+        stmt.accept(self)
+    }
+
+    fn emit(&mut self, byte: u8, span: Span) {
+        self.chunk().write(byte, span);
+    }
+    fn emit_op(&mut self, op: OpCode, span: Span) {
+        self.emit(op as u8, span);
+    }
+    fn emit_u16(&mut self, value: u16, span: Span) {
+        for byte in value.to_be_bytes() {
+            self.emit(byte, span);
+        }
+    }
+    fn emit_with_constant(&mut self, op: OpCode, value: Value, span: Span) -> Result<(), MainError> {
+        let constant = self.make_constant(value, span)?;
+        self.emit_op(op, span);
+        self.emit_u16(constant, span);
+        Ok(())
+    }
+    fn make_constant(&mut self, value: Value, span: Span) -> Result<u16, MainError> {
+        let key = MapKey::from_value(&value);
+        if let Some(constant) = key.as_ref().and_then(|key| self.current().constants.get(key)) {
+            return Ok(*constant);
+        }
+        let constant = self.chunk().add_constant(value);
+        let Ok(constant) = u16::try_from(constant) else {
+            return Err(compile_error(span, "Too many constants in one function."));
+        };
+        if let Some(key) = key {
+            self.current().constants.insert(key, constant);
+        }
+        Ok(constant)
+    }
+    fn identifier(&mut self, name: &str, span: Span) -> Result<u16, MainError> {
+        self.make_constant(Value::String(name.to_string()), span)
+    }
+    //Emits a jump with a placeholder offset and hands back where the offset has to be patched
+    fn emit_jump(&mut self, op: OpCode, span: Span) -> usize {
+        self.emit_op(op, span);
+        self.emit_u16(u16::MAX, span);
+        self.chunk().code.len() - 2
+    }
+    //Points the jump at `offset` to the next instruction
+    fn patch_jump(&mut self, offset: usize, span: Span) -> Result<(), MainError> {
+        let jump = self.chunk().code.len() - offset - 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            return Err(compile_error(span, "Too much code to jump over."));
+        };
+        let [high, low] = jump.to_be_bytes();
+        self.chunk().code[offset] = high;
+        self.chunk().code[offset + 1] = low;
+        Ok(())
+    }
+    fn emit_loop(&mut self, start: usize, span: Span) -> Result<(), MainError> {
+        self.emit_op(OpCode::Loop, span);
+        let Ok(jump) = u16::try_from(self.chunk().code.len() - start + 2) else {
+            return Err(compile_error(span, "Loop body too large."));
+        };
+        self.emit_u16(jump, span);
+        Ok(())
+    }
+    //Falling off the end of a function returns nil, or `this` from an initializer
+    fn emit_return(&mut self, span: Span) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit_op(OpCode::GetLocal, span);
+            self.emit(0, span);
+        } else {
+            self.emit_op(OpCode::Nil, span);
+        }
+        self.emit_op(OpCode::Return, span);
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().depth += 1;
+    }
+    fn end_scope(&mut self, span: Span) {
+        self.current().depth -= 1;
+        let depth = self.current().depth;
+        self.discard_locals(depth, span);
+        let state = self.current();
+        while state.locals.last().is_some_and(|local| local.depth > depth) {
+            state.locals.pop();
+        }
+    }
+    //Emits the code taking every local deeper than `depth` off the stack, the compiler still knows about them
+    fn discard_locals(&mut self, depth: usize, span: Span) {
+        let captured: Vec<bool> =
+            self.current().locals.iter().rev().take_while(|local| local.depth > depth).map(|local| local.captured).collect();
+        for captured in captured {
+            self.emit_op(if captured { OpCode::CloseUpvalue } else { OpCode::Pop }, span);
+        }
+    }
+    //The value of the local has to be on top of the stack already
+    fn add_local(&mut self, name: &str, span: Span) -> Result<(), MainError> {
+        let state = self.current();
+        if state.locals.len() > u8::MAX as usize {
+            return Err(compile_error(span, "Too many local variables in function."));
+        }
+        let depth = state.depth;
+        state.locals.push(Local { name: name.to_string(), depth, captured: false });
+        Ok(())
+    }
+    //Globals are defined by name, locals simply stay where their value was pushed
+    fn define_variable(&mut self, name: &Token) -> Result<(), MainError> {
+        if self.current().depth > 0 {
+            return self.add_local(&name.lexeme, name.span);
+        }
+        let constant = self.identifier(&name.lexeme, name.span)?;
+        self.emit_op(OpCode::DefineGlobal, name.span);
+        self.emit_u16(constant, name.span);
+        Ok(())
+    }
+    fn resolve_local(&self, function: usize, name: &str) -> Option<u8> {
+        let locals = &self.functions[function].locals;
+        locals.iter().rposition(|local| local.name == name).map(|slot| slot as u8)
+    }
+    fn resolve_upvalue(&mut self, function: usize, name: &str, span: Span) -> Result<Option<u8>, MainError> {
+        if function == 0 {
+            return Ok(None);
+        }
+        if let Some(slot) = self.resolve_local(function - 1, name) {
+            self.functions[function - 1].locals[slot as usize].captured = true;
+            return self.add_upvalue(function, UpvalueRef { index: slot, is_local: true }, span).map(Some);
+        }
+        match self.resolve_upvalue(function - 1, name, span)? {
+            Some(index) => self.add_upvalue(function, UpvalueRef { index, is_local: false }, span).map(Some),
+            None => Ok(None),
+        }
+    }
+    fn add_upvalue(&mut self, function: usize, upvalue: UpvalueRef, span: Span) -> Result<u8, MainError> {
+        let upvalues = &mut self.functions[function].upvalues;
+        if let Some(index) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(index as u8);
+        }
+        if upvalues.len() > u8::MAX as usize {
+            return Err(compile_error(span, "Too many closure variables in function."));
+        }
+        upvalues.push(upvalue);
+        Ok((upvalues.len() - 1) as u8)
+    }
+    //Reads the variable, or assigns the value on top of the stack to it
+    fn named_variable(&mut self, name: &str, span: Span, assign: bool) -> Result<(), MainError> {
+        let function = self.functions.len() - 1;
+        let (get, set, operand) = if let Some(slot) = self.resolve_local(function, name) {
+            (OpCode::GetLocal, OpCode::SetLocal, slot as u16)
+        } else if let Some(index) = self.resolve_upvalue(function, name, span)? {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index as u16)
+        } else {
+            let constant = self.identifier(name, span)?;
+            self.emit_op(if assign { OpCode::SetGlobal } else { OpCode::GetGlobal }, span);
+            self.emit_u16(constant, span);
+            return Ok(());
+        };
+        self.emit_op(if assign { set } else { get }, span);
+        self.emit(operand as u8, span);
+        Ok(())
+    }
+
+    //Compiles the function in its own chunk and leaves a closure over it on the stack
+    fn function(&mut self, declaration: &Function, kind: FunctionKind) -> Result<(), MainError> {
+        self.functions.push(FunctionState::new(declaration.name.lexeme.clone(), kind));
+        self.current().function.arity = declaration.params.len() as u32;
+        self.current().function.is_getter = declaration.is_getter;
+        self.begin_scope();
+        for param in &declaration.params {
+            self.add_local(&param.lexeme, param.span)?;
+        }
+        for statement in declaration.body.clone().iter_mut() {
+            self.statement(statement)?;
+        }
+        self.emit_return(declaration.span);
+        let state = self.functions.pop().unwrap();
+        let mut function = state.function;
+        function.upvalue_count = state.upvalues.len();
+        let constant = self.make_constant(Value::Vm(Object::Function(Rc::new(function))), declaration.span)?;
+        self.emit_op(OpCode::Closure, declaration.span);
+        self.emit_u16(constant, declaration.span);
+        for upvalue in state.upvalues {
+            self.emit(upvalue.is_local as u8, declaration.span);
+            self.emit(upvalue.index, declaration.span);
+        }
+        Ok(())
+    }
+    fn jump_out_of_loop(&mut self, keyword: &Token, is_break: bool) -> Result<(), MainError> {
+        let Some(depth) = self.current().loops.last().map(|l| l.depth) else {
+            return Err(compile_error(keyword.span, "Jump outside of a loop."));
+        };
+        self.discard_locals(depth, keyword.span);
+        let start = self.current().loops.last().and_then(|l| l.start);
+        match start {
+            Some(start) if !is_break => self.emit_loop(start, keyword.span)?,
+            _ => {
+                let jump = self.emit_jump(OpCode::Jump, keyword.span);
+                let current = self.current().loops.last_mut().unwrap();
+                if is_break { current.breaks.push(jump) } else { current.continues.push(jump) }
+            }
+        }
+        Ok(())
+    }
+    fn push_loop(&mut self, start: Option<usize>) {
+        let depth = self.current().depth;
+        self.current().loops.push(Loop { depth, start, continues: Vec::new(), breaks: Vec::new() });
+    }
+}
+fn compile_error(span: Span, message: &str) -> MainError {
+    MainError::CompileError((span, String::new(), message.to_string()))
+}
+
+impl VisitorExpr<Result<(), MainError>> for Compiler {
+    fn visit_literal_exp(&mut self, expr: &mut Literal) -> Result<(), MainError> {
+        match expr.literal.type_ {
+            TokenType::NIL => self.emit_op(OpCode::Nil, expr.span),
+            TokenType::TRUE => self.emit_op(OpCode::True, expr.span),
+            TokenType::FALSE => self.emit_op(OpCode::False, expr.span),
+            _ => self.emit_with_constant(OpCode::Constant, Value::from_literal(expr.literal.literal.clone()), expr.span)?,
+        }
+        Ok(())
+    }
+    fn visit_binary_exp(&mut self, expr: &mut Binary) -> Result<(), MainError> {
+        self.expression(&mut expr.left)?;
+        self.expression(&mut expr.right)?;
+        let op = match expr.operator.type_ {
+            TokenType::PLUS => OpCode::Add,
+            TokenType::MINUS => OpCode::Subtract,
+            TokenType::STAR => OpCode::Multiply,
+            TokenType::SLASH => OpCode::Divide,
+            TokenType::GREATER => OpCode::Greater,
+            TokenType::GreaterEqual => OpCode::GreaterEqual,
+            TokenType::LESS => OpCode::Less,
+            TokenType::LessEqual => OpCode::LessEqual,
+            TokenType::EqualEqual => OpCode::Equal,
+            _ => OpCode::NotEqual,
+        };
+        self.emit_op(op, expr.operator.span);
+        Ok(())
+    }
+    fn visit_unary_exp(&mut self, expr: &mut Unary) -> Result<(), MainError> {
+        self.expression(&mut expr.expr)?;
+        let op = if expr.operator.type_ == TokenType::MINUS { OpCode::Negate } else { OpCode::Not };
+        self.emit_op(op, expr.operator.span);
+        Ok(())
+    }
+    fn visit_grouping_exp(&mut self, expr: &mut Grouping) -> Result<(), MainError> {
+        self.expression(&mut expr.expr)
+    }
+    //The left operand stays on the stack as the result when it decides the outcome
+    fn visit_logical_exp(&mut self, expr: &mut Logical) -> Result<(), MainError> {
+        let span = expr.operator.span;
+        self.expression(&mut expr.left)?;
+        if expr.operator.type_ == TokenType::OR {
+            let else_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+            let end_jump = self.emit_jump(OpCode::Jump, span);
+            self.patch_jump(else_jump, span)?;
+            self.emit_op(OpCode::Pop, span);
+            self.expression(&mut expr.right)?;
+            self.patch_jump(end_jump, span)
+        } else {
+            let end_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+            self.emit_op(OpCode::Pop, span);
+            self.expression(&mut expr.right)?;
+            self.patch_jump(end_jump, span)
+        }
+    }
+    fn visit_call_exp(&mut self, expr: &mut Call) -> Result<(), MainError> {
+        self.expression(&mut expr.callee)?;
+        for argument in expr.arguments.iter_mut() {
+            self.expression(argument)?;
+        }
+        self.emit_op(OpCode::Call, expr.paren.span);
+        self.emit(expr.arguments.len() as u8, expr.paren.span);
+        Ok(())
+    }
+    fn visit_variable_exp(&mut self, expr: &mut Variable) -> Result<(), MainError> {
+        self.named_variable(&expr.var.lexeme, expr.var.span, false)
+    }
+    fn visit_assign_exp(&mut self, expr: &mut Assign) -> Result<(), MainError> {
+        self.expression(&mut expr.value)?;
+        self.named_variable(&expr.name.lexeme, expr.name.span, true)
+    }
+    fn visit_get_exp(&mut self, expr: &mut Get) -> Result<(), MainError> {
+        self.expression(&mut expr.object)?;
+        let constant = self.identifier(&expr.name.lexeme, expr.name.span)?;
+        self.emit_op(OpCode::GetProperty, expr.name.span);
+        self.emit_u16(constant, expr.name.span);
+        Ok(())
+    }
+    fn visit_set_exp(&mut self, expr: &mut Set) -> Result<(), MainError> {
+        self.expression(&mut expr.object)?;
+        self.expression(&mut expr.value)?;
+        let constant = self.identifier(&expr.name.lexeme, expr.name.span)?;
+        self.emit_op(OpCode::SetProperty, expr.name.span);
+        self.emit_u16(constant, expr.name.span);
+        Ok(())
+    }
+    fn visit_this_exp(&mut self, expr: &mut This) -> Result<(), MainError> {
+        self.named_variable("this", expr.keyword.span, false)
+    }
+    fn visit_super_exp(&mut self, expr: &mut Super) -> Result<(), MainError> {
+        let span = expr.keyword.span;
+        self.named_variable("this", span, false)?;
+        self.named_variable("super", span, false)?;
+        let constant = self.identifier(&expr.method.lexeme, span)?;
+        self.emit_op(OpCode::GetSuper, span);
+        self.emit_u16(constant, span);
+        Ok(())
+    }
+    fn visit_lambda_exp(&mut self, expr: &mut Lambda) -> Result<(), MainError> {
+        self.function(&expr.declaration, FunctionKind::Function)
+    }
+    fn visit_list_exp(&mut self, expr: &mut ListLiteral) -> Result<(), MainError> {
+        let Ok(count) = u16::try_from(expr.elements.len()) else {
+            return Err(compile_error(expr.bracket.span, "Too many elements in list literal."));
+        };
+        for element in expr.elements.iter_mut() {
+            self.expression(element)?;
+        }
+        self.emit_op(OpCode::BuildList, expr.bracket.span);
+        self.emit_u16(count, expr.bracket.span);
+        Ok(())
+    }
+    //Entries are inserted one at a time, so a bad key is reported where it was written
+    fn visit_map_exp(&mut self, expr: &mut MapLiteral) -> Result<(), MainError> {
+        self.emit_op(OpCode::NewMap, expr.brace.span);
+        for (key, value) in expr.entries.iter_mut() {
+            self.expression(key)?;
+            self.expression(value)?;
+            self.emit_op(OpCode::MapInsert, key.span());
+        }
+        Ok(())
+    }
+    fn visit_index_exp(&mut self, expr: &mut Index) -> Result<(), MainError> {
+        self.expression(&mut expr.object)?;
+        self.expression(&mut expr.index)?;
+        self.emit_op(OpCode::GetIndex, expr.bracket.span);
+        Ok(())
+    }
+    fn visit_index_set_exp(&mut self, expr: &mut IndexSet) -> Result<(), MainError> {
+        self.expression(&mut expr.object)?;
+        self.expression(&mut expr.index)?;
+        self.expression(&mut expr.value)?;
+        self.emit_op(OpCode::SetIndex, expr.bracket.span);
+        Ok(())
+    }
+}
+
+impl VisitorStmt<Result<(), MainError>> for Compiler {
+    fn visit_expression_stmt(&mut self, expr: &mut Expr) -> Result<(), MainError> {
+        self.expression(expr)?;
+        self.emit_op(OpCode::Pop, expr.span());
+        Ok(())
+    }
+    fn visit_print_stmt(&mut self, stmt: &mut Print) -> Result<(), MainError> {
+        self.expression(&mut stmt.expr)?;
+        self.emit_op(OpCode::Print, stmt.span);
+        Ok(())
+    }
+    fn visit_variable_stmt(&mut self, stmt: &mut Var) -> Result<(), MainError> {
+        match &mut stmt.initializer {
+            Some(initializer) => self.expression(initializer)?,
+            None => self.emit_op(OpCode::Nil, stmt.name.span),
+        }
+        self.define_variable(&stmt.name)
+    }
+    fn visit_block_stmt(&mut self, stmt: &mut Block) -> Result<(), MainError> {
+        self.begin_scope();
+        for statement in stmt.list.iter_mut() {
+            self.statement(statement)?;
+        }
+        self.end_scope(stmt.span);
+        Ok(())
+    }
+    fn visit_if_stmt(&mut self, stmt: &mut If) -> Result<(), MainError> {
+        let span = stmt.condition.span();
+        self.expression(&mut stmt.condition)?;
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+        self.emit_op(OpCode::Pop, span);
+        self.statement(&mut stmt.then_branch)?;
+        let else_jump = self.emit_jump(OpCode::Jump, span);
+        self.patch_jump(then_jump, span)?;
+        self.emit_op(OpCode::Pop, span);
+        if let Some(else_branch) = &mut stmt.else_branch {
+            self.statement(else_branch)?;
+        }
+        self.patch_jump(else_jump, span)
+    }
+    fn visit_while_stmt(&mut self, stmt: &mut While) -> Result<(), MainError> {
+        let span = stmt.condition.span();
+        let start = self.chunk().code.len();
+        self.expression(&mut stmt.condition)?;
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+        self.emit_op(OpCode::Pop, span);
+        self.push_loop(None);
+        self.statement(&mut stmt.body)?;
+        let current = self.current().loops.pop().unwrap();
+        for jump in current.continues {
+            self.patch_jump(jump, span)?;
+        }
+        if let Some(increment) = &mut stmt.increment {
+            self.expression(increment)?;
+            self.emit_op(OpCode::Pop, span);
+        }
+        self.emit_loop(start, span)?;
+        self.patch_jump(exit_jump, span)?;
+        self.emit_op(OpCode::Pop, span);
+        for jump in current.breaks {
+            self.patch_jump(jump, span)?;
+        }
+        Ok(())
+    }
+    //The cursor is a hidden local below the loop variable, it is the top of the stack whenever an iteration starts
+    fn visit_for_in_stmt(&mut self, stmt: &mut ForIn) -> Result<(), MainError> {
+        let span = stmt.keyword.span;
+        self.expression(&mut stmt.iterable)?;
+        self.emit_op(OpCode::Iterate, span);
+        self.begin_scope();
+        self.add_local(CURSOR, span)?;
+        let start = self.chunk().code.len();
+        let exit_jump = self.emit_jump(OpCode::ForNext, span);
+        self.push_loop(Some(start));
+        self.begin_scope();
+        self.add_local(&stmt.name.lexeme, stmt.name.span)?;
+        self.statement(&mut stmt.body)?;
+        self.end_scope(span);
+        let current = self.current().loops.pop().unwrap();
+        self.emit_loop(start, span)?;
+        self.patch_jump(exit_jump, span)?;
+        for jump in current.breaks {
+            self.patch_jump(jump, span)?;
+        }
+        self.end_scope(span);
+        Ok(())
+    }
+    fn visit_fn_stmt(&mut self, stmt: &mut Function) -> Result<(), MainError> {
+        //A local function is in scope inside its own body, so it can call itself
+        if self.current().depth > 0 {
+            self.add_local(&stmt.name.lexeme, stmt.name.span)?;
+            return self.function(stmt, FunctionKind::Function);
+        }
+        self.function(stmt, FunctionKind::Function)?;
+        self.define_variable(&stmt.name)
+    }
+    fn visit_return_stmt(&mut self, stmt: &mut Return) -> Result<(), MainError> {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit_return(stmt.keyword.span);
+            return Ok(());
+        }
+        self.expression(&mut stmt.value)?;
+        self.emit_op(OpCode::Return, stmt.keyword.span);
+        Ok(())
+    }
+    fn visit_class_stmt(&mut self, stmt: &mut Class) -> Result<(), MainError> {
+        let name = stmt.name.clone();
+        let constant = self.identifier(&name.lexeme, name.span)?;
+        self.emit_op(OpCode::Class, name.span);
+        self.emit_u16(constant, name.span);
+        self.define_variable(&name)?;
+        if let Some(superclass) = &stmt.superclass {
+            self.named_variable(&superclass.var.lexeme, superclass.var.span, false)?;
+            //Methods reach the superclass through a local called `super` in a scope around them
+            self.begin_scope();
+            self.add_local("super", superclass.var.span)?;
+            self.named_variable(&name.lexeme, name.span, false)?;
+            self.emit_op(OpCode::Inherit, name.span);
+        }
+        //The class stays on the stack while its methods are added to it
+        self.named_variable(&name.lexeme, name.span, false)?;
+        for method in &stmt.methods {
+            let kind = if method.name.lexeme == "init" { FunctionKind::Initializer } else { FunctionKind::Method };
+            self.function(method, kind)?;
+            let constant = self.identifier(&method.name.lexeme, method.name.span)?;
+            self.emit_op(OpCode::Method, method.name.span);
+            self.emit_u16(constant, method.name.span);
+        }
+        for method in &stmt.static_methods {
+            self.function(method, FunctionKind::Method)?;
+            let constant = self.identifier(&method.name.lexeme, method.name.span)?;
+            self.emit_op(OpCode::StaticMethod, method.name.span);
+            self.emit_u16(constant, method.name.span);
+        }
+        self.emit_op(OpCode::Pop, name.span);
+        if stmt.superclass.is_some() {
+            self.end_scope(name.span);
+        }
+        Ok(())
+    }
+    fn visit_break_stmt(&mut self, stmt: &mut Break) -> Result<(), MainError> {
+        self.jump_out_of_loop(&stmt.keyword, true)
+    }
+    fn visit_continue_stmt(&mut self, stmt: &mut Continue) -> Result<(), MainError> {
+        self.jump_out_of_loop(&stmt.keyword, false)
+    }
+}
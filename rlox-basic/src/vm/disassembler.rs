@@ -0,0 +1,102 @@
+//!Human readable listing of compiled bytecode
+//!
+//!Every instruction is printed on its own line as its offset, the source line it came from (`|` when
+//!it is the same as for the instruction before), the opcode and its operands
+use super::chunk::{Chunk, OpCode};
+use super::object::{Function, Object};
+use crate::value::Value;
+use std::fmt::Write;
+
+//Lists the function and every function compiled inside of it, outermost first
+pub fn disassemble(function: &Function) -> String {
+    let mut out = String::new();
+    disassemble_function(function, &mut out);
+    out
+}
+fn disassemble_function(function: &Function, out: &mut String) {
+    out.push_str(&disassemble_chunk(&function.chunk, &function.to_string()));
+    for constant in &function.chunk.constants {
+        if let Value::Vm(Object::Function(nested)) = constant {
+            out.push('\n');
+            disassemble_function(nested, out);
+        }
+    }
+}
+
+pub fn disassemble_chunk(chunk: &Chunk, name: &str) -> String {
+    let mut out = format!("== {} ==\n", name);
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(chunk, offset, &mut out);
+    }
+    out
+}
+
+//Appends the instruction at `offset` to `out` and hands back the offset of the next one
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize, out: &mut String) -> usize {
+    let line = chunk.spans[offset].line;
+    if offset > 0 && chunk.spans[offset - 1].line == line {
+        let _ = write!(out, "{:04}    | ", offset);
+    } else {
+        let _ = write!(out, "{:04} {:>4} ", offset, line);
+    }
+    let Some(op) = OpCode::from_byte(chunk.code[offset]) else {
+        let _ = writeln!(out, "Unknown opcode {}", chunk.code[offset]);
+        return offset + 1;
+    };
+    let name = format!("{:?}", op);
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method
+        | OpCode::StaticMethod => {
+            let constant = chunk.read_u16(offset + 1);
+            let _ = writeln!(out, "{:<16} {:4} '{}'", name, constant, chunk.constants[constant as usize]);
+            offset + 3
+        }
+        OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Call => {
+            let _ = writeln!(out, "{:<16} {:4}", name, chunk.code[offset + 1]);
+            offset + 2
+        }
+        OpCode::BuildList => {
+            let _ = writeln!(out, "{:<16} {:4}", name, chunk.read_u16(offset + 1));
+            offset + 3
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::ForNext => {
+            let target = offset + 3 + chunk.read_u16(offset + 1) as usize;
+            let _ = writeln!(out, "{:<16} {:4} -> {}", name, offset, target);
+            offset + 3
+        }
+        OpCode::Loop => {
+            let target = offset + 3 - chunk.read_u16(offset + 1) as usize;
+            let _ = writeln!(out, "{:<16} {:4} -> {}", name, offset, target);
+            offset + 3
+        }
+        OpCode::Closure => {
+            let constant = chunk.read_u16(offset + 1);
+            let function = &chunk.constants[constant as usize];
+            let _ = writeln!(out, "{:<16} {:4} {}", name, constant, function);
+            let upvalue_count = match function {
+                Value::Vm(Object::Function(function)) => function.upvalue_count,
+                _ => 0,
+            };
+            let mut offset = offset + 3;
+            for _ in 0..upvalue_count {
+                let kind = if chunk.code[offset] == 1 { "local" } else { "upvalue" };
+                let _ = writeln!(out, "{:04}    |                     {} {}", offset, kind, chunk.code[offset + 1]);
+                offset += 2;
+            }
+            offset
+        }
+        _ => {
+            let _ = writeln!(out, "{}", name);
+            offset + 1
+        }
+    }
+}
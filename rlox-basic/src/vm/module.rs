@@ -0,0 +1,398 @@
+//!Precompiled `.loxc` modules, the bytecode of a script saved so it can run without the front end
+//!
+//!Layout, integers are little endian and strings are a u32 length followed by UTF-8:
+//! - magic `LOXC` and the format version as a u16
+//! - CRC-32 of everything after it as a u32
+//! - name and text of the source, kept so runtime errors can still point into it
+//! - the script function
+//!
+//!A function is its name, arity (u32), upvalue count (u32), getter flag (u8), code, line table and
+//!constants. The line table is run length encoded as pairs of a count and a span, spans being relative
+//!to the start of the source. Constants are tagged with a byte: 0 number (f64), 1 string, 2 function
+//!
+//!The checksum only catches damage, so the code of every function is verified as well before the VM
+//!gets to trust it
+use super::chunk::{Chunk, OpCode};
+use super::object::{Function, Object};
+use crate::tokens::Span;
+use crate::value::Value;
+use crate::MainError;
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+
+pub const MAGIC: &[u8; 4] = b"LOXC";
+//Bumped whenever the layout or the meaning of an opcode changes
+pub const VERSION: u16 = 1;
+const HEADER: usize = MAGIC.len() + 2 + 4;
+
+//What a module holds besides its code
+pub struct Module {
+    pub function: Rc<Function>,
+    pub source_name: String,
+    pub source: String,
+}
+
+pub fn is_module(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+//`origin` is the byte offset the source starts at in the session that compiled it
+pub fn encode(function: &Function, source_name: &str, source: &str, origin: usize) -> Result<Vec<u8>, MainError> {
+    let mut body = Vec::new();
+    write_str(&mut body, source_name);
+    write_str(&mut body, source);
+    write_function(&mut body, function, origin)?;
+    let mut bytes = Vec::with_capacity(HEADER + body.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&crc32(&body).to_le_bytes());
+    bytes.extend_from_slice(&body);
+    Ok(bytes)
+}
+
+//`origin` is where the source of the module is placed in the session running it, spans are moved there
+pub fn decode(bytes: &[u8], origin: usize) -> Result<Module, MainError> {
+    if !is_module(bytes) {
+        return Err(invalid("Not a compiled Lox module."));
+    }
+    let mut reader = Reader { bytes, at: MAGIC.len() };
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(invalid(&format!("Module has format version {} but version {} is supported.", version, VERSION)));
+    }
+    let checksum = reader.u32()?;
+    if crc32(&bytes[HEADER..]) != checksum {
+        return Err(invalid("Module checksum does not match, the file is corrupt."));
+    }
+    let source_name = reader.string()?;
+    let source = reader.string()?;
+    let function = reader.function(origin)?;
+    if reader.at != bytes.len() {
+        return Err(invalid("Unexpected data after the end of the module."));
+    }
+    Ok(Module { function, source_name, source })
+}
+
+fn invalid(message: &str) -> MainError {
+    MainError::Standard(Box::new(io::Error::new(io::ErrorKind::InvalidData, message.to_string())))
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+fn write_str(out: &mut Vec<u8>, text: &str) {
+    write_u32(out, text.len());
+    out.extend_from_slice(text.as_bytes());
+}
+fn write_span(out: &mut Vec<u8>, span: Span, origin: usize) {
+    write_u32(out, span.start.saturating_sub(origin));
+    write_u32(out, span.end.saturating_sub(origin));
+    out.extend_from_slice(&span.line.to_le_bytes());
+    write_u32(out, span.column);
+}
+fn write_function(out: &mut Vec<u8>, function: &Function, origin: usize) -> Result<(), MainError> {
+    write_str(out, &function.name);
+    write_u32(out, function.arity as usize);
+    write_u32(out, function.upvalue_count);
+    out.push(function.is_getter as u8);
+    let chunk = &function.chunk;
+    write_u32(out, chunk.code.len());
+    out.extend_from_slice(&chunk.code);
+    let mut runs: Vec<(usize, Span)> = Vec::new();
+    for span in &chunk.spans {
+        match runs.last_mut() {
+            Some((count, last)) if last == span => *count += 1,
+            _ => runs.push((1, *span)),
+        }
+    }
+    write_u32(out, runs.len());
+    for (count, span) in runs {
+        write_u32(out, count);
+        write_span(out, span, origin);
+    }
+    write_u32(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Value::Number(n) => {
+                out.push(0);
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::String(s) => {
+                out.push(1);
+                write_str(out, s);
+            }
+            Value::Vm(Object::Function(nested)) => {
+                out.push(2);
+                write_function(out, nested, origin)?;
+            }
+            other => return Err(invalid(&format!("Constant {} can't be saved in a module.", other))),
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MainError> {
+        let Some(taken) = self.bytes.get(self.at..self.at.saturating_add(len)) else {
+            return Err(invalid("Unexpected end of module."));
+        };
+        self.at += len;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, MainError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, MainError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, MainError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn len(&mut self) -> Result<usize, MainError> {
+        self.u32().map(|len| len as usize)
+    }
+    fn string(&mut self) -> Result<String, MainError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid("Module holds a string that is not UTF-8."))
+    }
+    fn span(&mut self, origin: usize) -> Result<Span, MainError> {
+        let start = self.len()? + origin;
+        let end = self.len()? + origin;
+        let line = i32::from_le_bytes(self.take(4)?.try_into().unwrap());
+        let column = self.len()?;
+        Ok(Span::new(start, end, line, column))
+    }
+    fn function(&mut self, origin: usize) -> Result<Rc<Function>, MainError> {
+        let name = self.string()?;
+        let arity = self.u32()?;
+        let upvalue_count = self.len()?;
+        let is_getter = self.u8()? != 0;
+        let len = self.len()?;
+        let code = self.take(len)?.to_vec();
+        let mut spans = Vec::with_capacity(code.len());
+        for _ in 0..self.len()? {
+            let count = self.len()?;
+            let span = self.span(origin)?;
+            if spans.len() + count > code.len() {
+                return Err(invalid("Module line table is longer than its code."));
+            }
+            spans.extend(std::iter::repeat_n(span, count));
+        }
+        if spans.len() != code.len() {
+            return Err(invalid("Module line table does not cover its code."));
+        }
+        let mut constants = Vec::new();
+        for _ in 0..self.len()? {
+            let constant = match self.u8()? {
+                0 => Value::Number(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+                1 => Value::String(self.string()?),
+                2 => Value::Vm(Object::Function(self.function(origin)?)),
+                tag => return Err(invalid(&format!("Unknown constant tag {} in module.", tag))),
+            };
+            constants.push(constant);
+        }
+        let chunk = Chunk { code, constants, spans };
+        let function = Function { name, arity, upvalue_count, is_getter, chunk };
+        verify(&function)?;
+        Ok(Rc::new(function))
+    }
+}
+
+//Rejects code the compiler could not have produced: unknown opcodes, operands past the end, constants
+//or upvalues that don't exist, jumps that don't land on an instruction and code that doesn't end in a return.
+//Then every path through the code is followed to check the stack never runs out of values and locals exist
+fn verify(function: &Function) -> Result<(), MainError> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+    //Opcode and length of the instruction starting at every offset
+    let mut instructions = vec![None; code.len()];
+    let mut jumps = Vec::new();
+    let mut last = None;
+    let mut offset = 0;
+    while offset < code.len() {
+        let bad = |problem: &str| invalid(&format!("Module code of {} {} at offset {}.", function, problem, offset));
+        let operand = |at: usize| code.get(offset + at).copied().ok_or_else(|| bad("has an instruction cut short"));
+        let wide = |at: usize| Ok::<usize, MainError>(u16::from_be_bytes([operand(at)?, operand(at + 1)?]) as usize);
+        let constant = |at: usize| {
+            let index = wide(at)?;
+            chunk.constants.get(index).ok_or_else(|| bad(&format!("refers to missing constant {}", index)))
+        };
+        let Some(op) = OpCode::from_byte(code[offset]) else {
+            return Err(bad(&format!("has unknown opcode {}", code[offset])));
+        };
+        let len = match op {
+            OpCode::Constant => {
+                constant(1)?;
+                3
+            }
+            OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method
+            | OpCode::StaticMethod => {
+                if !matches!(constant(1)?, Value::String(_)) {
+                    return Err(bad("uses a constant that is not a name"));
+                }
+                3
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                if operand(1)? as usize >= function.upvalue_count {
+                    return Err(bad(&format!("refers to missing upvalue {}", operand(1)?)));
+                }
+                2
+            }
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::Call => {
+                operand(1)?;
+                2
+            }
+            OpCode::BuildList => {
+                wide(1)?;
+                3
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::ForNext => {
+                jumps.push((offset, (offset + 3).checked_add(wide(1)?)));
+                3
+            }
+            OpCode::Loop => {
+                jumps.push((offset, (offset + 3).checked_sub(wide(1)?)));
+                3
+            }
+            OpCode::Closure => {
+                let Value::Vm(Object::Function(nested)) = constant(1)? else {
+                    return Err(bad("makes a closure of a constant that is not a function"));
+                };
+                for i in 0..nested.upvalue_count {
+                    let (is_local, index) = (operand(3 + 2 * i)?, operand(4 + 2 * i)?);
+                    if is_local > 1 || (is_local == 0 && index as usize >= function.upvalue_count) {
+                        return Err(bad(&format!("captures missing upvalue {}", index)));
+                    }
+                }
+                3 + 2 * nested.upvalue_count
+            }
+            _ => 1,
+        };
+        instructions[offset] = Some((op, len));
+        last = Some(op);
+        offset += len;
+    }
+    let mut targets = HashMap::new();
+    for (offset, target) in jumps {
+        match target {
+            Some(target) if instructions.get(target).is_some_and(Option::is_some) => targets.insert(offset, target),
+            _ => return Err(invalid(&format!("Module code of {} jumps outside of an instruction at offset {}.", function, offset))),
+        };
+    }
+    if last != Some(OpCode::Return) {
+        return Err(invalid(&format!("Module code of {} does not end in a return.", function)));
+    }
+    verify_stack(function, &instructions, &targets)
+}
+
+//Follows every path from the start of the function with the number of values on its part of the stack,
+//the callee and the arguments to begin with. An instruction has to be reached with the same depth on
+//every path, code no path reaches is never run and left alone
+fn verify_stack(function: &Function, instructions: &[Option<(OpCode, usize)>], targets: &HashMap<usize, usize>) -> Result<(), MainError> {
+    let code = &function.chunk.code;
+    let mut depths = vec![None; code.len()];
+    let mut pending = vec![(0, function.arity as usize + 1)];
+    while let Some((offset, depth)) = pending.pop() {
+        let bad = |problem: &str| invalid(&format!("Module code of {} {} at offset {}.", function, problem, offset));
+        match depths[offset] {
+            Some(known) if known == depth => continue,
+            Some(_) => return Err(bad("is reached with different stack depths")),
+            None => depths[offset] = Some(depth),
+        }
+        //Every offset put on the list starts an instruction, the first pass made sure of it
+        let (op, len) = instructions[offset].expect("paths only lead to instructions");
+        let operand = |at: usize| code[offset + at] as usize;
+        let (pops, pushes) = match op {
+            OpCode::Constant
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetLocal
+            | OpCode::GetGlobal
+            | OpCode::GetUpvalue
+            | OpCode::Closure
+            | OpCode::Class
+            | OpCode::NewMap => (0, 1),
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::Print | OpCode::CloseUpvalue => (1, 0),
+            OpCode::SetLocal
+            | OpCode::SetGlobal
+            | OpCode::SetUpvalue
+            | OpCode::GetProperty
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::JumpIfFalse
+            | OpCode::Iterate
+            | OpCode::Return => (1, 1),
+            OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::GetIndex
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide => (2, 1),
+            //The class stays below the superclass or method taken off it
+            OpCode::Inherit | OpCode::Method | OpCode::StaticMethod => (2, 1),
+            OpCode::SetIndex | OpCode::MapInsert => (3, 1),
+            OpCode::Call => (operand(1) + 1, 1),
+            OpCode::BuildList => (u16::from_be_bytes([code[offset + 1], code[offset + 2]]) as usize, 1),
+            //The cursor stays, the next element is pushed on top of it unless the loop is done
+            OpCode::ForNext => (1, 1),
+            OpCode::Jump | OpCode::Loop => (0, 0),
+        };
+        if pops > depth {
+            return Err(bad("takes more values than are on the stack"));
+        }
+        let slots = match op {
+            OpCode::GetLocal | OpCode::SetLocal => vec![operand(1)],
+            OpCode::Closure => {
+                let Value::Vm(Object::Function(nested)) = &function.chunk.constants[u16::from_be_bytes([code[offset + 1], code[offset + 2]]) as usize] else {
+                    unreachable!("the first pass checked the constant");
+                };
+                (0..nested.upvalue_count).filter(|i| operand(3 + 2 * i) == 1).map(|i| operand(4 + 2 * i)).collect()
+            }
+            _ => Vec::new(),
+        };
+        if let Some(slot) = slots.into_iter().find(|&slot| slot >= depth) {
+            return Err(bad(&format!("refers to local slot {} past the top of the stack", slot)));
+        }
+        let after = depth - pops + pushes;
+        match op {
+            OpCode::Return => {}
+            OpCode::Jump | OpCode::Loop => pending.push((targets[&offset], after)),
+            OpCode::JumpIfFalse => pending.extend([(targets[&offset], after), (offset + len, after)]),
+            OpCode::ForNext => pending.extend([(targets[&offset], after), (offset + len, after + 1)]),
+            _ => pending.push((offset + len, after)),
+        }
+    }
+    Ok(())
+}
+
+//CRC-32 as used by zip and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
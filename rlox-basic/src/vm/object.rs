@@ -0,0 +1,175 @@
+//!Heap objects of the bytecode VM, values on its stack point at them through `Value::Vm`
+use super::chunk::Chunk;
+use crate::gc::{self, Traced};
+use crate::value::{Cursor, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+//A function as the compiler leaves it, the same function can be closed over many times
+#[derive(Debug, Default)]
+pub struct Function {
+    //Empty for the top-level script
+    pub name: String,
+    pub arity: u32,
+    pub upvalue_count: usize,
+    pub is_getter: bool,
+    pub chunk: Chunk,
+}
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "<script>")
+        } else {
+            write!(f, "<fn {}>", self.name)
+        }
+    }
+}
+
+//A variable captured by a closure, it stays on the stack until the scope declaring it ends
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+//Methods of the superclass are copied in when the class inherits, so lookups never walk a chain
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub methods: RefCell<HashMap<String, Rc<Closure>>>,
+    //Methods called on the class object itself, `this` inside them is the class
+    pub static_methods: RefCell<HashMap<String, Rc<Closure>>>,
+}
+impl Class {
+    pub fn new(name: String) -> Class {
+        Class { name, methods: RefCell::new(HashMap::new()), static_methods: RefCell::new(HashMap::new()) }
+    }
+    pub fn find_method(&self, name: &str) -> Option<Rc<Closure>> {
+        self.methods.borrow().get(name).cloned()
+    }
+    pub fn find_static_method(&self, name: &str) -> Option<Rc<Closure>> {
+        self.static_methods.borrow().get(name).cloned()
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: HashMap<String, Value>,
+}
+
+//A method along with the object `this` refers to inside of it
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Object {
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    BoundMethod(Rc<BoundMethod>),
+    //Hidden local of a running for-in loop
+    Cursor(Rc<RefCell<Cursor>>),
+}
+impl Object {
+    //Objects are equal only to themselves
+    pub fn same_as(&self, other: &Object) -> bool {
+        match (self, other) {
+            (Object::Function(l), Object::Function(r)) => Rc::ptr_eq(l, r),
+            (Object::Closure(l), Object::Closure(r)) => Rc::ptr_eq(l, r),
+            (Object::Class(l), Object::Class(r)) => Rc::ptr_eq(l, r),
+            (Object::Instance(l), Object::Instance(r)) => Rc::ptr_eq(l, r),
+            (Object::BoundMethod(l), Object::BoundMethod(r)) => Rc::ptr_eq(l, r),
+            (Object::Cursor(l), Object::Cursor(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Function(function) => write!(f, "{}", function),
+            Object::Closure(closure) => write!(f, "{}", closure.function),
+            Object::Class(class) => write!(f, "{}", class.name),
+            Object::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+            Object::BoundMethod(bound) => write!(f, "{}", bound.method.function),
+            Object::Cursor(_) => write!(f, "<iterator>"),
+        }
+    }
+}
+
+impl Traced for Closure {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        self.upvalues.iter().for_each(|upvalue| edges.push(gc::address(upvalue)));
+        true
+    }
+    //Closures can't change, the cycles through them are broken up at their upvalues
+    fn clear(&self) {}
+}
+impl Traced for RefCell<Upvalue> {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        let Ok(upvalue) = self.try_borrow() else {
+            return false;
+        };
+        //Open upvalues point at the stack, which is a root anyway
+        if let Upvalue::Closed(value) = &*upvalue {
+            gc::trace_value(value, edges);
+        }
+        true
+    }
+    fn clear(&self) {
+        if let Ok(mut upvalue) = self.try_borrow_mut() {
+            *upvalue = Upvalue::Closed(Value::Nil);
+        }
+    }
+}
+impl Traced for Class {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        let (Ok(methods), Ok(static_methods)) = (self.methods.try_borrow(), self.static_methods.try_borrow()) else {
+            return false;
+        };
+        methods.values().chain(static_methods.values()).for_each(|method| edges.push(gc::address(method)));
+        true
+    }
+    fn clear(&self) {
+        if let (Ok(mut methods), Ok(mut static_methods)) = (self.methods.try_borrow_mut(), self.static_methods.try_borrow_mut()) {
+            methods.clear();
+            static_methods.clear();
+        }
+    }
+}
+impl Traced for RefCell<Instance> {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        let Ok(instance) = self.try_borrow() else {
+            return false;
+        };
+        edges.push(gc::address(&instance.class));
+        instance.fields.values().for_each(|value| gc::trace_value(value, edges));
+        true
+    }
+    fn clear(&self) {
+        if let Ok(mut instance) = self.try_borrow_mut() {
+            instance.fields.clear();
+        }
+    }
+}
+impl Traced for BoundMethod {
+    fn trace(&self, edges: &mut Vec<usize>) -> bool {
+        gc::trace_value(&self.receiver, edges);
+        edges.push(gc::address(&self.method));
+        true
+    }
+    fn clear(&self) {}
+}
@@ -0,0 +1,85 @@
+//!Drives the debugger with scripted commands and checks where it stops and what it shows
+use rlox_basic::debugger::Debugger;
+use rlox_basic::{Diagnostic, Lox};
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+
+const SCRIPT: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = 1;
+var y = add(x, 2);
+print y;
+";
+
+//Runs `source` under the debugger fed with `commands`, hands back what the debugger and the program wrote
+fn debug(source: &str, commands: &str) -> (String, String, Vec<Diagnostic>) {
+    let console = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let debugger = Debugger::new(source, Box::new(Cursor::new(commands.to_string())), console.clone());
+    let mut lox = Lox::new();
+    lox.set_output(output.clone());
+    lox.set_debugger(Some(Rc::new(RefCell::new(debugger))));
+    let diagnostics = lox.eval(source).err().unwrap_or_default();
+    let console = String::from_utf8(console.borrow().clone()).unwrap();
+    let output = String::from_utf8(output.borrow().clone()).unwrap();
+    (console, output, diagnostics)
+}
+
+#[test]
+fn stops_at_breakpoints_and_inspects_the_frame() {
+    let (console, output, diagnostics) = debug(SCRIPT, "break 3\ncontinue\nbacktrace\nprint sum * 10\nenv\ncontinue\n");
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    assert_eq!(output, "3\n");
+    assert!(console.starts_with("Stopped at line 1\n"), "{}", console);
+    assert!(console.contains("Breakpoint at line 3\n      3 |   return sum;\n"), "{}", console);
+    assert!(console.contains("#0 add (line 3)\n#1 <script> (line 6)\n"), "{}", console);
+    assert!(console.contains("(rlox) 30\n"), "{}", console);
+    assert!(console.contains("#lev 2\n  a = 1\n  b = 2\n  sum = 3\n#lev 1\n  add = <fn add>\n  x = 1\n"), "{}", console);
+}
+
+#[test]
+fn steps_into_over_and_out_of_calls() {
+    let stops = |commands: &str| -> Vec<String> {
+        let (console, _, _) = debug(SCRIPT, commands);
+        console.lines().filter(|line| line.contains("at line")).map(|line| line.replace("(rlox) ", "")).collect()
+    };
+    //The declaration of `add`, then `var x`, then the call
+    assert_eq!(stops("next\nnext\nnext\n"), ["Stopped at line 1", "Stopped at line 5", "Stopped at line 6", "Stopped at line 7"]);
+    assert_eq!(stops("next\nnext\nstep\n"), ["Stopped at line 1", "Stopped at line 5", "Stopped at line 6", "Stopped at line 2"]);
+    assert_eq!(stops("next\nnext\nstep\nfinish\n"), ["Stopped at line 1", "Stopped at line 5", "Stopped at line 6", "Stopped at line 2", "Stopped at line 7"]);
+}
+
+#[test]
+fn evaluates_in_the_scope_of_a_method() {
+    let source = "\
+class Counter {
+  init() { this.count = 0; }
+  bump(by) {
+    this.count = this.count + by;
+    return this.count;
+  }
+}
+var counter = Counter();
+counter.bump(5);
+";
+    let (console, _, diagnostics) = debug(source, "b 5\nc\np this.count + by\np missing\nc\n");
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    assert!(console.contains("(rlox) 10\n"), "{}", console);
+    assert!(console.contains("Error: missing: Undeclared variable or Function\n"), "{}", console);
+}
+
+#[test]
+fn quitting_stops_the_program() {
+    //Quitting is not an error, the program just ends where it stopped
+    let (_, output, diagnostics) = debug(SCRIPT, "b 7\nc\nquit\n");
+    assert_eq!(output, "");
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    //Without more commands the program runs to the end
+    let (_, output, diagnostics) = debug(SCRIPT, "");
+    assert!(diagnostics.is_empty());
+    assert_eq!(output, "3\n");
+}
@@ -0,0 +1,41 @@
+//!Checks the bytecode listing the disassembler prints for small scripts
+use rlox_basic::Lox;
+
+fn disassemble(source: &str) -> String {
+    let mut lox = Lox::new();
+    lox.set_color(false);
+    lox.disassemble(source).unwrap()
+}
+
+#[test]
+fn lists_offsets_lines_operands_and_constants() {
+    let listing = disassemble("var a = 1;\nprint a + 2;\n");
+    let expected = "\
+== <script> ==
+0000    1 Constant            0 '1'
+0003    | DefineGlobal        1 'a'
+0006    2 GetGlobal           1 'a'
+0009    | Constant            2 '2'
+0012    | Add
+0013    | Print
+0014    | Nil
+0015    | Return
+";
+    assert_eq!(listing, expected);
+}
+
+#[test]
+fn lists_nested_functions_after_the_script() {
+    let listing = disassemble("fun outer() {\n  var x = 1;\n  fun inner() { return x; }\n  return inner;\n}\n");
+    let headers: Vec<&str> = listing.lines().filter(|line| line.starts_with("==")).collect();
+    assert_eq!(headers, ["== <script> ==", "== <fn outer> ==", "== <fn inner> =="]);
+    assert!(listing.contains("Closure             1 <fn inner>\n0006    |                     local 1\n"), "{}", listing);
+    assert!(listing.contains("GetUpvalue          0\n"), "{}", listing);
+}
+
+#[test]
+fn static_errors_are_reported_instead_of_a_listing() {
+    let mut lox = Lox::new();
+    let diagnostics = lox.disassemble("print ;").unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+}
@@ -0,0 +1,140 @@
+//!Uses `Lox` the way a host program would, several runs in one session
+use rlox_basic::diagnostics::Kind;
+use rlox_basic::{Diagnostic, Engine, Lox, Value};
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::rc::Rc;
+
+fn line(diagnostics: &[Diagnostic]) -> i32 {
+    diagnostics[0].span.unwrap().line
+}
+
+#[test]
+fn every_run_counts_lines_from_one() {
+    let mut lox = Lox::new();
+    lox.set_color(false);
+    let script = env::temp_dir().join(format!("rlox-embedding-{}.lox", std::process::id()));
+    fs::write(&script, "var a = 1;\nvar b = 2;\nfun fail() {\n  return -nil;\n}\n").unwrap();
+    lox.run_file(script.to_str().unwrap()).unwrap();
+    fs::remove_file(&script).unwrap();
+
+    let diagnostics = lox.eval("var c = 3;\nprint nope;").unwrap_err();
+    assert_eq!(line(&diagnostics), 2);
+    let report = lox.render(&diagnostics[0]);
+    assert!(report.contains("--> <repl>:2:7\n"), "{}", report);
+    assert!(report.contains("2 | print nope;\n"), "{}", report);
+    assert_eq!(line(&lox.eval("print nope;").unwrap_err()), 1);
+    //A function defined by an earlier run still points into the script it came from
+    let diagnostics = lox.eval("fail();").unwrap_err();
+    assert_eq!(line(&diagnostics), 4);
+    let report = lox.render(&diagnostics[0]);
+    assert!(report.contains(&format!("--> {}:4:10\n", script.display())), "{}", report);
+    assert!(report.contains("4 |   return -nil;\n"), "{}", report);
+}
+
+#[test]
+fn eval_hands_back_a_trailing_expression() {
+    let mut lox = Lox::new();
+    assert_eq!(lox.eval("var a = 20; a + 1;").unwrap(), Value::Number(21.0));
+    assert_eq!(lox.eval("\"con\" + \"cat\";").unwrap(), Value::String("concat".to_string()));
+    //Without an expression at the end there is no value
+    assert_eq!(lox.eval("var b = 2;").unwrap(), Value::Nil);
+}
+
+#[test]
+fn globals_round_trip() {
+    let mut lox = Lox::new();
+    lox.set_global("x", Value::Number(20.0));
+    lox.eval("var y = x * 2; x = \"changed\";").unwrap();
+    assert_eq!(lox.get_global("y"), Some(Value::Number(40.0)));
+    assert_eq!(lox.get_global("x"), Some(Value::String("changed".to_string())));
+    assert_eq!(lox.get_global("missing"), None);
+}
+
+#[test]
+fn scripts_call_natives_of_the_host() {
+    let mut lox = Lox::new();
+    lox.define_native("twice", 1, |_, args| match &args[0] {
+        Value::Number(n) => Ok(Value::Number(n * 2.0)),
+        _ => Ok(Value::Nil),
+    });
+    assert_eq!(lox.eval("twice(21);").unwrap(), Value::Number(42.0));
+    assert_eq!(lox.eval("twice(\"no\");").unwrap(), Value::Nil);
+    let diagnostics = lox.eval("twice(1, 2);").unwrap_err();
+    assert_eq!(diagnostics[0].kind, Kind::Runtime);
+    assert_eq!(diagnostics[0].message, "Expected 1 argumetns but got 2 .");
+    assert_eq!(line(&diagnostics), 1);
+}
+
+#[test]
+fn output_goes_to_the_writer_of_the_host() {
+    let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let mut lox = Lox::new();
+    lox.set_output(output.clone());
+    lox.define_native("shout", 1, |interpretor, args| {
+        interpretor.write_line(&format!("{}!", args[0]))?;
+        Ok(Value::Nil)
+    });
+    lox.eval("print 1 + 2; shout(\"hey\"); print \"done\";").unwrap();
+    assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "3\nhey!\ndone\n");
+    //Both engines write to it
+    let mut lox = Lox::new();
+    lox.set_engine(Engine::Bytecode).unwrap();
+    lox.set_output(output.clone());
+    lox.define_native("shout", 1, |interpretor, args| {
+        interpretor.write_line(&format!("{}!", args[0]))?;
+        Ok(Value::Nil)
+    });
+    output.borrow_mut().clear();
+    lox.eval("print \"a\" + \"b\"; shout(3);").unwrap();
+    assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "ab\n3!\n");
+}
+
+#[test]
+fn closures_outlive_a_run_that_failed() {
+    let mut lox = Lox::new();
+    lox.set_engine(Engine::Bytecode).unwrap();
+    let source = "var f; fun mk() { var a = 1; var b = 2; var x = \"captured\"; fun g() { return x; } f = g; return nil.boom; } mk();";
+    assert_eq!(lox.eval(source).unwrap_err()[0].message, "Only instances have properties");
+    assert_eq!(lox.eval("f();").unwrap(), Value::String("captured".to_string()));
+    //Also when the error comes from a call the VM makes itself, here to drive an iterator
+    let source = "
+var h;
+class It { hasNext() { var y = \"kept\"; fun k() { return y; } h = k; return -nil; } iterator() { return this; } }
+for (var v in It()) {}";
+    assert_eq!(lox.eval(source).unwrap_err()[0].message, "Operand must be a number");
+    assert_eq!(lox.eval("h();").unwrap(), Value::String("kept".to_string()));
+}
+
+#[test]
+fn runaway_recursion_is_an_error() {
+    //The tree-walker's limit is sized for a thread with the stack of a main thread
+    let thread = std::thread::Builder::new().stack_size(8 << 20).spawn(|| {
+        for engine in [Engine::TreeWalk, Engine::Bytecode] {
+            let mut lox = Lox::new();
+            lox.set_engine(engine).unwrap();
+            let diagnostics = lox.eval("fun f(n) { if (n == 0) return 0; return 1 + f(n - 1); }\nf(100000);").unwrap_err();
+            assert_eq!(diagnostics[0].kind, Kind::Runtime);
+            assert_eq!(diagnostics[0].message, "Stack overflow.");
+            //Recursion within the limit still works
+            assert_eq!(lox.eval("f(200);").unwrap(), Value::Number(200.0));
+        }
+    });
+    thread.unwrap().join().unwrap();
+}
+
+#[test]
+fn the_engine_is_kept_once_code_ran() {
+    let mut lox = Lox::new();
+    lox.set_engine(Engine::Bytecode).unwrap();
+    lox.set_engine(Engine::TreeWalk).unwrap();
+    //Code that doesn't get past the front end doesn't count
+    assert!(lox.eval("fun (").is_err());
+    lox.set_engine(Engine::Bytecode).unwrap();
+    lox.eval("fun f() { return 1; }").unwrap();
+    lox.set_engine(Engine::Bytecode).unwrap();
+    let diagnostics = lox.set_engine(Engine::TreeWalk).unwrap_err();
+    assert_eq!(diagnostics[0].message, "The engine can't be changed after code ran in the session.");
+    assert_eq!(lox.eval("f();").unwrap(), Value::Number(1.0));
+}
@@ -0,0 +1,37 @@
+//!Checks the garbage collector through the library, every test runs on a thread and so a heap of its own
+use rlox_basic::{Lox, Value};
+
+#[test]
+fn stats_count_collections_and_freed_objects() {
+    let mut lox = Lox::new();
+    lox.eval("class Pair { init() { this.other = nil; } }\nfor (var i in [1, 2, 3]) { var p = Pair(); var q = Pair(); p.other = q; q.other = p; }").unwrap();
+    assert_eq!(lox.collect_garbage(), 6);
+    let stats = lox.gc_stats();
+    assert_eq!(stats.collections, 1);
+    assert_eq!(stats.last_freed, 6);
+    assert_eq!(stats.total_freed, 6);
+    assert!(stats.live > 0);
+    assert_eq!(lox.collect_garbage(), 0);
+    assert_eq!(lox.gc_stats().collections, 2);
+}
+
+#[test]
+fn values_held_by_the_host_are_roots() {
+    let mut lox = Lox::new();
+    let node = lox.eval("class Node {}\nfun make() { var n = Node(); n.self = n; return n; }\nmake();").unwrap();
+    lox.collect_garbage();
+    lox.set_global("kept", node);
+    assert_eq!(lox.eval("kept.self == kept;").unwrap(), Value::Boolean(true));
+}
+
+#[test]
+fn long_running_sessions_stay_bounded() {
+    let mut lox = Lox::new();
+    lox.eval("fun make() { var self; fun f() { return self; } self = f; }").unwrap();
+    for _ in 0..20 {
+        lox.eval("for (var i in slice([0,0,0,0,0,0,0,0,0,0], 0, 10)) make();").unwrap();
+    }
+    lox.collect_garbage();
+    assert!(lox.gc_stats().live < 100, "{:?}", lox.gc_stats());
+    assert_eq!(lox.gc_stats().total_freed, 200);
+}
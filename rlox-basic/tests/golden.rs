@@ -0,0 +1,160 @@
+//!Runs every script under `test_files/` and checks it against the annotations in its comments
+//!
+//! - `// expect: <text>` a line the script prints, in order
+//! - `// expect runtime error: <message>` the runtime error the script stops with, on the line of the comment
+//! - `// expect error: <message>` a scanning, parsing or resolving error on the line of the comment
+use rlox_basic::diagnostics::Kind;
+use rlox_basic::{Diagnostic, Engine, Lox};
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+const EXPECT_ERROR: &str = "// expect error: ";
+
+#[derive(Debug, PartialEq)]
+struct Expected {
+    output: Vec<String>,
+    //Line and message of every scanning, parsing and resolving error
+    errors: Vec<(i32, String)>,
+    runtime_error: Option<(i32, String)>,
+}
+
+fn parse_expectations(source: &str) -> Expected {
+    let mut expected = Expected { output: Vec::new(), errors: Vec::new(), runtime_error: None };
+    for (i, line) in source.lines().enumerate() {
+        let number = i as i32 + 1;
+        if let Some(at) = line.find(EXPECT) {
+            expected.output.push(line[at + EXPECT.len()..].to_string());
+        } else if let Some(at) = line.find(EXPECT_RUNTIME_ERROR) {
+            expected.runtime_error = Some((number, line[at + EXPECT_RUNTIME_ERROR.len()..].to_string()));
+        } else if let Some(at) = line.find(EXPECT_ERROR) {
+            expected.errors.push((number, line[at + EXPECT_ERROR.len()..].to_string()));
+        }
+    }
+    expected
+}
+
+fn lox_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            lox_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            files.push(path);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Run(Engine),
+    //Compiled to a `.loxc` module first, which is then run
+    Module,
+}
+
+fn run(lox: &mut Lox, path: &Path, mode: Mode) -> Vec<Diagnostic> {
+    let path = path.to_str().unwrap();
+    match mode {
+        Mode::Run(engine) => {
+            lox.set_engine(engine).unwrap();
+            lox.run_file(path).err().unwrap_or_default()
+        }
+        Mode::Module => {
+            let name = path.replace(['/', '\\', '.'], "_");
+            let module = env::temp_dir().join(format!("rlox-{}-{}.loxc", std::process::id(), name));
+            let module = module.to_str().unwrap();
+            let diagnostics = lox.compile_file(path, module).and_then(|_| lox.run_file(module).map(|_| ()));
+            let _ = fs::remove_file(module);
+            diagnostics.err().unwrap_or_default()
+        }
+    }
+}
+
+//Everything that differs from the annotations, empty when the script behaves as expected
+fn check(path: &Path, mode: Mode) -> Vec<String> {
+    let source = fs::read_to_string(path).unwrap();
+    let expected = parse_expectations(&source);
+    let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let mut lox = Lox::new();
+    lox.set_color(false);
+    lox.set_output(output.clone());
+    let diagnostics = run(&mut lox, path, mode);
+
+    let mut failures = Vec::new();
+    let printed = String::from_utf8(output.borrow().clone()).unwrap();
+    let printed: Vec<&str> = printed.lines().collect();
+    for (i, line) in expected.output.iter().enumerate() {
+        match printed.get(i) {
+            Some(actual) if actual == line => {}
+            Some(actual) => failures.push(format!("expected output {:?} but got {:?}", line, actual)),
+            None => failures.push(format!("missing expected output {:?}", line)),
+        }
+    }
+    for line in printed.iter().skip(expected.output.len()) {
+        failures.push(format!("unexpected output {:?}", line));
+    }
+
+    let mut actual_errors = Vec::new();
+    let mut actual_runtime_error = None;
+    for diagnostic in &diagnostics {
+        let line = diagnostic.span.map_or(0, |span| span.line);
+        match diagnostic.kind {
+            Kind::Runtime => actual_runtime_error = Some((line, diagnostic.message.clone())),
+            _ => actual_errors.push((line, diagnostic.message.clone())),
+        }
+    }
+    if actual_runtime_error != expected.runtime_error {
+        failures.push(format!("expected runtime error {:?} but got {:?}", expected.runtime_error, actual_runtime_error));
+    }
+    if actual_errors != expected.errors {
+        failures.push(format!("expected errors {:?} but got {:?}", expected.errors, actual_errors));
+    }
+    failures
+}
+
+//Both engines, and modules compiled ahead of time, have to agree with every script
+fn run_golden_files(mode: Mode) {
+    let mut files = Vec::new();
+    lox_files(&Path::new(env!("CARGO_MANIFEST_DIR")).join("test_files"), &mut files);
+    assert!(!files.is_empty(), "no scripts found in test_files");
+    let mut report = String::new();
+    for path in &files {
+        for failure in check(path, mode) {
+            report.push_str(&format!("{}: {}\n", path.display(), failure));
+        }
+    }
+    assert!(report.is_empty(), "\n{}", report);
+}
+
+#[test]
+fn golden_files() {
+    run_golden_files(Mode::Run(Engine::TreeWalk));
+}
+
+#[test]
+fn golden_files_bytecode() {
+    run_golden_files(Mode::Run(Engine::Bytecode));
+}
+
+#[test]
+fn golden_files_compiled_module() {
+    run_golden_files(Mode::Module);
+}
+
+#[test]
+fn annotations_are_parsed() {
+    let expected = parse_expectations("print 1; // expect: 1\n\nprint x; // expect runtime error: Oops\nvar; // expect error: Bad\n");
+    assert_eq!(
+        expected,
+        Expected {
+            output: vec!["1".to_string()],
+            errors: vec![(4, "Bad".to_string())],
+            runtime_error: Some((3, "Oops".to_string())),
+        }
+    );
+}
@@ -0,0 +1,143 @@
+//!Checks that `.loxc` modules run on their own and that damaged ones are turned away
+use rlox_basic::diagnostics::Kind;
+use rlox_basic::tokens::Span;
+use rlox_basic::vm::chunk::{Chunk, OpCode};
+use rlox_basic::vm::module;
+use rlox_basic::vm::object::{Function, Object};
+use rlox_basic::{Diagnostic, Lox, Value};
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+//Compiles `source` to a module and hands back the bytes of it, the files are deleted again
+fn compile(name: &str, source: &str) -> Vec<u8> {
+    let script = env::temp_dir().join(format!("rlox-module-{}-{}.lox", std::process::id(), name));
+    let module = script.with_extension("loxc");
+    fs::write(&script, source).unwrap();
+    Lox::new().compile_file(script.to_str().unwrap(), module.to_str().unwrap()).unwrap();
+    let bytes = fs::read(&module).unwrap();
+    fs::remove_file(&script).unwrap();
+    fs::remove_file(&module).unwrap();
+    bytes
+}
+
+//Runs the module from a file of its own and hands back what it printed
+fn run(name: &str, bytes: &[u8]) -> (String, Vec<Diagnostic>) {
+    let module: PathBuf = env::temp_dir().join(format!("rlox-module-{}-{}-run.loxc", std::process::id(), name));
+    fs::write(&module, bytes).unwrap();
+    let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let mut lox = Lox::new();
+    lox.set_output(output.clone());
+    let diagnostics = lox.run_file(module.to_str().unwrap()).err().unwrap_or_default();
+    fs::remove_file(&module).unwrap();
+    let printed = String::from_utf8(output.borrow().clone()).unwrap();
+    (printed, diagnostics)
+}
+
+#[test]
+fn runs_without_the_script() {
+    let bytes = compile("runs", "fun add(a, b) { return a + b; }\nprint add(1, 2);\nprint -nil;\n");
+    assert!(bytes.starts_with(b"LOXC"));
+    let (printed, diagnostics) = run("runs", &bytes);
+    assert_eq!(printed, "3\n");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].kind, Kind::Runtime);
+    assert_eq!(diagnostics[0].message, "Operand must be a number");
+    //Spans still point into the script the module was compiled from
+    let span = diagnostics[0].span.unwrap();
+    assert_eq!((span.line, span.column), (3, 7));
+}
+
+#[test]
+fn rejects_a_corrupt_module() {
+    let mut bytes = compile("corrupt", "print \"intact\";\n");
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    let (printed, diagnostics) = run("corrupt", &bytes);
+    assert_eq!(printed, "");
+    assert_eq!(diagnostics[0].kind, Kind::Standard);
+    assert!(diagnostics[0].message.contains("checksum"), "{}", diagnostics[0].message);
+}
+
+#[test]
+fn rejects_another_format_version() {
+    let mut bytes = compile("version", "print 1;\n");
+    bytes[4] += 1;
+    let (printed, diagnostics) = run("version", &bytes);
+    assert_eq!(printed, "");
+    assert!(diagnostics[0].message.contains("format version 2"), "{}", diagnostics[0].message);
+}
+
+//A module with an intact checksum around the given code, which no compiler produced
+fn forged(code: Vec<u8>, constants: Vec<Value>) -> Vec<u8> {
+    let spans = vec![Span::default(); code.len()];
+    let function = Function { chunk: Chunk { code, constants, spans }, ..Function::default() };
+    module::encode(&function, "forged.lox", "", 0).unwrap()
+}
+
+#[test]
+fn rejects_code_that_is_not_consistent() {
+    let nil_return = [OpCode::Nil as u8, OpCode::Return as u8];
+    let cases: Vec<(Vec<u8>, Vec<Value>, &str)> = vec![
+        (vec![200, OpCode::Return as u8], vec![], "unknown opcode 200"),
+        (vec![OpCode::Constant as u8, 0, 3, OpCode::Return as u8], vec![Value::Number(1.0)], "missing constant 3"),
+        (vec![OpCode::GetGlobal as u8, 0, 0, OpCode::Return as u8], vec![Value::Number(1.0)], "not a name"),
+        (vec![OpCode::GetUpvalue as u8, 0, OpCode::Return as u8], vec![], "missing upvalue 0"),
+        (vec![OpCode::Jump as u8, 0, 2, OpCode::Nil as u8, OpCode::Return as u8], vec![], "jumps outside"),
+        (vec![OpCode::Loop as u8, 0, 9, OpCode::Return as u8], vec![], "jumps outside"),
+        (vec![OpCode::Constant as u8, 0], vec![], "cut short"),
+        (vec![OpCode::Nil as u8], vec![], "does not end in a return"),
+        ([&[OpCode::Closure as u8, 0, 0][..], &nil_return].concat(), vec![Value::Number(1.0)], "not a function"),
+        //Only the script itself is in slot 0, the stack has to hold what every instruction takes
+        (vec![OpCode::GetLocal as u8, 200, OpCode::Return as u8], vec![], "local slot 200"),
+        (vec![OpCode::Add as u8, OpCode::Return as u8], vec![], "more values than are on the stack"),
+        (vec![OpCode::Pop as u8, OpCode::Pop as u8, OpCode::Nil as u8, OpCode::Return as u8], vec![], "more values than are on the stack"),
+        (
+            vec![OpCode::CloseUpvalue as u8, OpCode::CloseUpvalue as u8, OpCode::Nil as u8, OpCode::Return as u8],
+            vec![],
+            "more values than are on the stack",
+        ),
+        //Both ways into the return, one of them with an extra value
+        (
+            vec![OpCode::True as u8, OpCode::JumpIfFalse as u8, 0, 1, OpCode::Nil as u8, OpCode::Return as u8],
+            vec![],
+            "different stack depths",
+        ),
+    ];
+    for (code, constants, problem) in cases {
+        let (printed, diagnostics) = run("forged", &forged(code, constants));
+        assert_eq!(printed, "");
+        assert_eq!(diagnostics[0].kind, Kind::Standard);
+        assert!(diagnostics[0].message.contains(problem), "{} should mention {}", diagnostics[0].message, problem);
+    }
+    //Upvalues captured by a nested function have to exist in the one creating the closure
+    let nested = Function { name: "inner".to_string(), upvalue_count: 1, ..Function::default() };
+    let nested = Value::Vm(Object::Function(Rc::new(Function {
+        chunk: Chunk { code: nil_return.to_vec(), constants: Vec::new(), spans: vec![Span::default(); 2] },
+        ..nested
+    })));
+    let code = [&[OpCode::Closure as u8, 0, 0, 0, 5][..], &nil_return].concat();
+    let (_, diagnostics) = run("forged", &forged(code, vec![nested]));
+    assert!(diagnostics[0].message.contains("captures missing upvalue 5"), "{}", diagnostics[0].message);
+}
+
+#[test]
+fn functions_of_a_module_stay_callable() {
+    let bytes = compile("session", "fun twice(n) { return 2 * n; }\n");
+    let module = env::temp_dir().join(format!("rlox-module-{}-session-run.loxc", std::process::id()));
+    fs::write(&module, &bytes).unwrap();
+    let module = module.to_str().unwrap();
+    //The session moves to the VM, so code run after the module can call into it
+    let mut lox = Lox::new();
+    lox.run_file(module).unwrap();
+    assert_eq!(lox.eval("twice(21);").unwrap(), Value::Number(42.0));
+    //Functions of the tree-walker couldn't be called from the module
+    let mut lox = Lox::new();
+    lox.eval("fun inc(n) { return n + 1; }").unwrap();
+    let diagnostics = lox.run_file(module).unwrap_err();
+    assert_eq!(diagnostics[0].kind, Kind::Standard);
+    assert!(diagnostics[0].message.starts_with("Modules run on the VM"), "{}", diagnostics[0].message);
+    fs::remove_file(module).unwrap();
+}
@@ -0,0 +1,53 @@
+//!Checks the calls runtime errors are traced through
+use rlox_basic::{Engine, Lox};
+
+const SCRIPT: &str = "\
+class A {
+  broken { return nil.x; }
+  class staticBroken { return -nil; }
+}
+class B < A {
+  viaSuper { return super.broken; }
+}
+class P { init(n) { this.n = -n; } }
+";
+
+//Notes of the error `code`, placed on the line after `SCRIPT`, stops with on `engine`
+fn trace(engine: Engine, code: &str) -> Vec<String> {
+    let mut lox = Lox::new();
+    lox.set_engine(engine).unwrap();
+    let diagnostics = lox.eval(&format!("{}{}", SCRIPT, code)).unwrap_err();
+    diagnostics[0].notes.clone()
+}
+
+#[test]
+fn getters_have_frames_of_their_own() {
+    for engine in [Engine::TreeWalk, Engine::Bytecode] {
+        assert_eq!(trace(engine, "B().viaSuper;"), ["at broken (line 2)", "at viaSuper (line 6)", "at <script> (line 9)"]);
+        assert_eq!(trace(engine, "A.staticBroken;"), ["at staticBroken (line 3)", "at <script> (line 9)"]);
+    }
+}
+
+#[test]
+fn initializers_are_the_frame_of_a_class_call() {
+    for engine in [Engine::TreeWalk, Engine::Bytecode] {
+        assert_eq!(trace(engine, "P(nil);"), ["at init (line 8)", "at <script> (line 9)"]);
+    }
+}
+
+#[test]
+fn recursion_is_folded() {
+    //The tree-walker's recursion needs the stack of a main thread
+    let thread = std::thread::Builder::new().stack_size(8 << 20).spawn(|| {
+        for engine in [Engine::TreeWalk, Engine::Bytecode] {
+            let notes = trace(engine, "fun f(n) { if (n == 0) return -nil; return f(n - 1); }\nf(100);");
+            assert_eq!(notes, ["at f (line 9)", "... repeated 100 more times", "at <script> (line 10)"]);
+            //Calls that differ are kept, up to the innermost ones and the script
+            let notes = trace(engine, "fun a(n) { return b(n); }\nfun b(n) { if (n == 0) return -nil; return a(n - 1); }\na(100);");
+            assert_eq!(notes.len(), 16);
+            assert_eq!(notes[..3], ["at b (line 10)", "at a (line 9)", "at b (line 10)"]);
+            assert_eq!(notes[14..], ["... 188 more frames", "at <script> (line 11)"]);
+        }
+    });
+    thread.unwrap().join().unwrap();
+}